@@ -0,0 +1,448 @@
+use std::io;
+use std::path::Path;
+
+use wav::bit_depth::BitDepth;
+use wav::header::Header;
+
+use crate::{frames, open_wav, wav_files_in, Sample};
+
+// All samples scaled to -1.0..1.0, still interleaved.
+pub fn samples_f32(data: &BitDepth) -> Vec<f32> {
+    fn scale<T: Sample>(d: &[T]) -> Vec<f32> {
+        d.iter().map(|s| s.to_f32()).collect()
+    }
+    match data {
+        BitDepth::Eight(d) => scale(d),
+        BitDepth::Sixteen(d) => scale(d),
+        BitDepth::TwentyFour(d) => scale(d),
+        BitDepth::ThirtyTwoFloat(d) => scale(d),
+        BitDepth::Empty => Vec::new(),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StereoMetrics {
+    // RMS of (L - R) / 2 and (L + R) / 2
+    pub side_rms: f32,
+    pub mid_rms: f32,
+    // Pearson correlation of L and R, 0.0 when either channel is silent
+    pub correlation: f32,
+    // side level as a share of mid plus side, 0 to 100: 0 is dual mono, 50
+    // is two unrelated channels and 100 is one channel inverted against the
+    // other; near-identical channels land just above 0.
+    pub width: f32,
+    // 0.0 to 1.0, where 1.0 is certainly mono: width rescaled so unrelated
+    // channels score 0, times the correlation. Level and sample rate don't
+    // enter into it, so scores compare across files. Silence scores 0.
+    pub mono_confidence: f32,
+}
+
+// Compensated (Kahan) summation, so sums over hours of audio keep the
+// small terms a plain f64 sum would round away.
+#[derive(Debug, Clone, Copy, Default)]
+struct KahanSum {
+    sum: f64,
+    compensation: f64,
+}
+
+impl KahanSum {
+    fn add(&mut self, value: f64) {
+        let y = value - self.compensation;
+        let t = self.sum + y;
+        self.compensation = (t - self.sum) - y;
+        self.sum = t;
+    }
+}
+
+pub(crate) fn sample_count(data: &BitDepth) -> usize {
+    match data {
+        BitDepth::Eight(d) => d.len(),
+        BitDepth::Sixteen(d) => d.len(),
+        BitDepth::TwentyFour(d) => d.len(),
+        BitDepth::ThirtyTwoFloat(d) => d.len(),
+        BitDepth::Empty => 0,
+    }
+}
+
+// Mid/side levels of a 2-channel file; None for any other channel count.
+pub fn stereo_metrics(header: &Header, data: &BitDepth) -> Option<StereoMetrics> {
+    if header.channel_count != 2 {
+        return None;
+    }
+    let samples = samples_f32(data);
+    let [mut side, mut mid, mut lr, mut ll, mut rr] = [KahanSum::default(); 5];
+    let mut count = 0usize;
+    for frame in frames(&samples, 2) {
+        let (l, r) = (frame[0] as f64, frame[1] as f64);
+        side.add(((l - r) / 2.0).powi(2));
+        mid.add(((l + r) / 2.0).powi(2));
+        lr.add(l * r);
+        ll.add(l * l);
+        rr.add(r * r);
+        count += 1;
+    }
+    let [side, mid, lr, ll, rr] = [side, mid, lr, ll, rr].map(|s| s.sum);
+    if count == 0 {
+        return None;
+    }
+    let correlation = if ll > 0.0 && rr > 0.0 {
+        lr / (ll * rr).sqrt()
+    } else {
+        0.0
+    };
+    let (side_rms, mid_rms) = ((side / count as f64).sqrt(), (mid / count as f64).sqrt());
+    let width = if side_rms + mid_rms > 0.0 {
+        100.0 * side_rms / (side_rms + mid_rms)
+    } else {
+        0.0
+    };
+    let mono_confidence = (1.0 - width / 50.0).max(0.0) * correlation.max(0.0);
+    Some(StereoMetrics {
+        side_rms: side_rms as f32,
+        mid_rms: mid_rms as f32,
+        correlation: correlation as f32,
+        width: width as f32,
+        mono_confidence: mono_confidence as f32,
+    })
+}
+
+// Frames of a 2-channel file where L and R differ by more than `tolerance`
+// (in -1.0..1.0 units), as (frame index, left, right). Stops after `limit`
+// frames; empty for any other channel count.
+pub fn diff_channels(
+    header: &Header,
+    data: &BitDepth,
+    tolerance: f32,
+    limit: usize,
+) -> Vec<(usize, f32, f32)> {
+    if header.channel_count != 2 {
+        return Vec::new();
+    }
+    let samples = samples_f32(data);
+    frames(&samples, 2)
+        .enumerate()
+        .filter(|(_, frame)| (frame[0] - frame[1]).abs() > tolerance)
+        .map(|(i, frame)| (i, frame[0], frame[1]))
+        .take(limit)
+        .collect()
+}
+
+// Bits actually in use, counting down from the most significant: a 24-bit
+// file padded up from 16-bit reports 16. None for float or empty data, and
+// 0 when every sample is digital silence.
+pub fn effective_bits(data: &BitDepth) -> Option<u16> {
+    // (all samples OR'd together, width of the container, bits per sample)
+    let (used, width, bits) = match data {
+        // 8-bit is unsigned, so re-center before looking at the low bits
+        BitDepth::Eight(d) => (d.iter().fold(0u32, |a, &s| a | (s ^ 0x80) as u32), 8, 8),
+        BitDepth::Sixteen(d) => (d.iter().fold(0u32, |a, &s| a | s as u16 as u32), 16, 16),
+        // 24-bit samples sit in the top three bytes of an i32
+        BitDepth::TwentyFour(d) => (d.iter().fold(0u32, |a, &s| a | s as u32), 32, 24),
+        BitDepth::ThirtyTwoFloat(_) | BitDepth::Empty => return None,
+    };
+    if sample_count(data) == 0 {
+        return None;
+    }
+    if used == 0 {
+        return Some(0);
+    }
+    Some((width - used.trailing_zeros() as u16).min(bits))
+}
+
+// A run of at least this many full-scale samples in a channel counts as clipping.
+const CLIP_RUN: usize = 3;
+const FULL_SCALE: f32 = 0.999;
+
+// Number of samples that sit in runs of consecutive full-scale values.
+pub fn clipped_samples(header: &Header, data: &BitDepth) -> usize {
+    let channel_count = header.channel_count.max(1);
+    // counted over the samples as stored, as a float copy of a long file
+    // would double the memory a conversion needs
+    match data {
+        BitDepth::Eight(d) => clipped_runs(d, channel_count),
+        BitDepth::Sixteen(d) => clipped_runs(d, channel_count),
+        BitDepth::TwentyFour(d) => clipped_runs(d, channel_count),
+        BitDepth::ThirtyTwoFloat(d) => clipped_runs(d, channel_count),
+        BitDepth::Empty => 0,
+    }
+}
+
+fn clipped_runs<T: Sample>(data: &[T], channel_count: u16) -> usize {
+    let mut runs = vec![0usize; channel_count as usize];
+    let mut clipped = 0;
+    for frame in frames(data, channel_count) {
+        for (run, s) in runs.iter_mut().zip(frame) {
+            if s.to_f32().abs() >= FULL_SCALE {
+                *run += 1;
+            } else {
+                if *run >= CLIP_RUN {
+                    clipped += *run;
+                }
+                *run = 0;
+            }
+        }
+    }
+    clipped + runs.into_iter().filter(|&r| r >= CLIP_RUN).sum::<usize>()
+}
+
+// Fraction of all samples that are clipped, 0.0 to 1.0.
+pub fn clip_ratio(header: &Header, data: &BitDepth) -> f32 {
+    let total = sample_count(data);
+    if total == 0 {
+        return 0.0;
+    }
+    clipped_samples(header, data) as f32 / total as f32
+}
+
+// Per-channel peak levels over `buckets` equal slices of the file, for
+// drawing a small waveform. Files shorter than `buckets` frames get one
+// bucket per frame.
+pub fn peak_envelope(header: &Header, data: &BitDepth, buckets: usize) -> Vec<Vec<f32>> {
+    let channel_count = header.channel_count.max(1) as usize;
+    let samples = samples_f32(data);
+    let frame_count = samples.len() / channel_count;
+    let buckets = buckets.min(frame_count);
+    let mut envelope = vec![vec![0.0f32; buckets]; channel_count];
+    for (i, frame) in frames(&samples, channel_count as u16).enumerate() {
+        let bucket = i * buckets / frame_count;
+        for (channel, &s) in envelope.iter_mut().zip(frame) {
+            channel[bucket] = channel[bucket].max(s.abs());
+        }
+    }
+    envelope
+}
+
+// Whether `mono` holds one channel of `source`, or the mean of all of them,
+// to within `tolerance` per sample (in -1.0..1.0 units). For auditing
+// conversions made earlier or by other tools.
+pub fn is_mono_reduction_of(mono: &Path, source: &Path, tolerance: f32) -> io::Result<bool> {
+    let (mono_header, mono_data) = open_wav(mono)?;
+    let (header, data) = open_wav(source)?;
+    let channel_count = header.channel_count as usize;
+    let mono = samples_f32(&mono_data);
+    let samples = samples_f32(&data);
+    if mono_header.channel_count != 1
+        || mono_header.sampling_rate != header.sampling_rate
+        || mono.len() != samples.len() / channel_count
+    {
+        return Ok(false);
+    }
+    let matches = |reduce: &dyn Fn(&[f32]) -> f32| {
+        frames(&samples, header.channel_count)
+            .zip(&mono)
+            .all(|(frame, &m)| (reduce(frame) - m).abs() <= tolerance)
+    };
+    let mean = |frame: &[f32]| frame.iter().sum::<f32>() / channel_count as f32;
+    Ok((0..channel_count).any(|c| matches(&|frame: &[f32]| frame[c])) || matches(&mean))
+}
+
+// One CSV row per `.wav` file in `dir`. The mid/side columns are left
+// empty for files that are not 2-channel. A file that can't be read gets a
+// row with only its name and the error, so one bad file doesn't lose the
+// report for the rest.
+pub fn metrics_csv(dir: &Path) -> io::Result<String> {
+    let mut csv = "file,channels,sample_rate,bits,side_rms,mid_rms,correlation,width,\
+                   mono_confidence,error\n"
+        .to_owned();
+    for path in wav_files_in(dir)? {
+        let name = csv_field(&path.file_name().unwrap().to_string_lossy());
+        let (header, data) = match open_wav(&path) {
+            Ok(wav) => wav,
+            Err(e) => {
+                csv += &format!("{},,,,,,,,,{}\n", name, csv_field(&e.to_string()));
+                continue;
+            }
+        };
+        let metrics = match stereo_metrics(&header, &data) {
+            Some(m) => format!(
+                "{},{},{},{},{}",
+                m.side_rms, m.mid_rms, m.correlation, m.width, m.mono_confidence
+            ),
+            None => ",,,,".to_owned(),
+        };
+        csv += &format!(
+            "{},{},{},{},{},\n",
+            name, header.channel_count, header.sampling_rate, header.bits_per_sample, metrics
+        );
+    }
+    Ok(csv)
+}
+
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{pcm16, write_wav_file};
+    use wav::header::WAV_FORMAT_PCM;
+
+    #[test]
+    fn test_diff_channels() {
+        let header = Header::new(WAV_FORMAT_PCM, 2, 48000, 16);
+        let mut frames = vec![[1000i16, 1000]; 8];
+        frames[5][1] = 1200;
+        let data = pcm16(&frames);
+        let diff = diff_channels(&header, &data, 0.001, 10);
+        assert_eq!(diff.len(), 1);
+        assert_eq!(diff[0].0, 5);
+        assert!(diff_channels(&header, &data, 0.01, 10).is_empty());
+    }
+
+    #[test]
+    fn test_effective_bits() {
+        let padded = BitDepth::TwentyFour(vec![0x1234_0000, -0x0100_0000, 0x7fff_0000]);
+        assert_eq!(effective_bits(&padded), Some(16));
+        let full = BitDepth::TwentyFour(vec![0x1234_0100, 0]);
+        assert_eq!(effective_bits(&full), Some(24));
+        assert_eq!(effective_bits(&pcm16(&[[4], [-8]])), Some(14));
+        assert_eq!(effective_bits(&BitDepth::Eight(vec![128, 130])), Some(7));
+        assert_eq!(effective_bits(&BitDepth::ThirtyTwoFloat(vec![0.5])), None);
+        assert_eq!(effective_bits(&BitDepth::Sixteen(vec![])), None);
+        assert_eq!(effective_bits(&BitDepth::Sixteen(vec![0, 0])), Some(0));
+    }
+
+    #[test]
+    fn test_kahan_sum_stays_accurate() {
+        let (mut stable, mut plain) = (KahanSum::default(), 0.0f64);
+        for _ in 0..10_000_000 {
+            stable.add(0.1);
+            plain += 0.1;
+        }
+        assert!((stable.sum - 1_000_000.0).abs() < 1e-6);
+        assert!((plain - 1_000_000.0).abs() > 1e-6);
+    }
+
+    #[test]
+    fn test_stereo_width() {
+        let header = Header::new(WAV_FORMAT_PCM, 2, 48000, 16);
+        let width = |frames: &[[i16; 2]]| stereo_metrics(&header, &pcm16(frames)).unwrap().width;
+        assert_eq!(width(&[[1000, 1000], [-500, -500]]), 0.0);
+        // a shared signal with a little of something else on the right
+        let narrow = width(&[[1000, 800], [-1000, -800], [1000, 800], [-1000, -800]]);
+        assert!((5.0..30.0).contains(&narrow), "{}", narrow);
+        assert_eq!(width(&[[1000, 0], [0, 1000]]), 50.0);
+        assert_eq!(width(&[[1000, -1000], [-500, 500]]), 100.0);
+    }
+
+    #[test]
+    fn test_mono_confidence() {
+        let header = Header::new(WAV_FORMAT_PCM, 2, 48000, 16);
+        let confidence = |frames: &[[i16; 2]]| {
+            stereo_metrics(&header, &pcm16(frames))
+                .unwrap()
+                .mono_confidence
+        };
+        assert!(confidence(&[[1000, 1000], [-700, -700], [300, 300]]) > 0.99);
+        let unrelated = [[1000, 0], [0, 1000], [-1000, 0], [0, -1000]];
+        assert!(confidence(&unrelated) < 0.01);
+        assert_eq!(confidence(&[[1000, -1000], [-500, 500]]), 0.0);
+    }
+
+    #[test]
+    fn test_is_mono_reduction_of() {
+        let dir = Path::new("test/reduction");
+        let _ = std::fs::remove_dir_all(dir);
+        let stereo = Header::new(WAV_FORMAT_PCM, 2, 44100, 16);
+        let source = dir.join("source.wav");
+        write_wav_file(&source, stereo, &pcm16(&[[1000, 3000], [-1000, -3000]]));
+        let mono = Header::new(WAV_FORMAT_PCM, 1, 44100, 16);
+        let left = dir.join("left.wav");
+        write_wav_file(&left, mono, &pcm16(&[[1000], [-1000]]));
+        let mean = dir.join("mean.wav");
+        write_wav_file(&mean, mono, &pcm16(&[[2000], [-2000]]));
+        let other = dir.join("other.wav");
+        write_wav_file(&other, mono, &pcm16(&[[1000], [1000]]));
+
+        assert!(is_mono_reduction_of(&left, &source, 0.0).unwrap());
+        assert!(is_mono_reduction_of(&mean, &source, 0.0001).unwrap());
+        assert!(!is_mono_reduction_of(&other, &source, 0.0001).unwrap());
+        assert!(!is_mono_reduction_of(&source, &source, 0.0).unwrap());
+    }
+
+    #[test]
+    fn test_metrics_csv() {
+        let dir = Path::new("test/metrics");
+        let _ = std::fs::remove_dir_all(dir);
+        let stereo = Header::new(WAV_FORMAT_PCM, 2, 48000, 16);
+        let dual = pcm16(&[[1000, 1000], [-1000, -1000]]);
+        write_wav_file(&dir.join("dual.wav"), stereo, &dual);
+        let mono = Header::new(WAV_FORMAT_PCM, 1, 44100, 16);
+        write_wav_file(&dir.join("mono.wav"), mono, &pcm16(&[[1], [2]]));
+        // three samples in a stereo file, so the last frame is cut short
+        let cut = BitDepth::Sixteen(vec![1000; 3]);
+        write_wav_file(&dir.join("cut.wav"), stereo, &cut);
+
+        let csv = metrics_csv(dir).unwrap();
+        let rows: Vec<Vec<&str>> = csv.lines().map(|l| l.split(',').collect()).collect();
+        assert_eq!(
+            rows[0],
+            [
+                "file",
+                "channels",
+                "sample_rate",
+                "bits",
+                "side_rms",
+                "mid_rms",
+                "correlation",
+                "width",
+                "mono_confidence",
+                "error"
+            ]
+        );
+        assert_eq!(rows.len(), 4);
+        assert_eq!(rows[1][..9], ["cut.wav", "", "", "", "", "", "", "", ""]);
+        assert!(rows[1][9].starts_with("Audio ends partway through a frame"));
+        assert_eq!(rows[2][..4], ["dual.wav", "2", "48000", "16"]);
+        assert_eq!(rows[2][4].parse::<f32>().unwrap(), 0.0);
+        assert!((rows[2][5].parse::<f32>().unwrap() - 1000.0 / 32768.0).abs() < 1e-6);
+        assert_eq!(rows[2][6].parse::<f32>().unwrap(), 1.0);
+        assert_eq!(rows[2][7].parse::<f32>().unwrap(), 0.0);
+        assert_eq!(rows[2][8].parse::<f32>().unwrap(), 1.0);
+        assert_eq!(rows[2][9], "");
+        assert_eq!(
+            rows[3],
+            ["mono.wav", "1", "44100", "16", "", "", "", "", "", ""]
+        );
+    }
+
+    #[test]
+    fn test_peak_envelope_buckets() {
+        let header = Header::new(WAV_FORMAT_PCM, 2, 100, 16);
+        // one second of audio with a silent right channel
+        let data: Vec<i16> = (0..100).flat_map(|i| [i * 100, 0]).collect();
+        let data = BitDepth::Sixteen(data);
+
+        let envelope = peak_envelope(&header, &data, 32);
+        assert_eq!(envelope.len(), 2);
+        assert!(envelope.iter().all(|c| c.len() == 32));
+        assert!(envelope[1].iter().all(|&p| p == 0.0));
+        assert_eq!(envelope[0][31], 9900.0 / 32768.0);
+        assert!(envelope[0].windows(2).all(|w| w[0] < w[1]));
+
+        assert_eq!(peak_envelope(&header, &data, 1000)[0].len(), 100);
+        assert_eq!(
+            peak_envelope(&header, &BitDepth::Sixteen(vec![]), 32)[0].len(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_clipping_detection() {
+        let header = Header::new(WAV_FORMAT_PCM, 2, 44100, 16);
+        // left clips for four frames, right touches full scale only twice
+        let data = BitDepth::Sixteen(vec![
+            1000, 32767, 32767, 32767, 32767, 0, 32767, 0, -32768, 0, 0, 0,
+        ]);
+        assert_eq!(clipped_samples(&header, &data), 4);
+        assert!((clip_ratio(&header, &data) - 4.0 / 12.0).abs() < 1e-6);
+
+        let clean = BitDepth::Sixteen(vec![32767, 0, 0, 32767, 1000, 1000]);
+        assert_eq!(clipped_samples(&header, &clean), 0);
+    }
+}
@@ -0,0 +1,144 @@
+use std::io;
+use std::io::{Read, Seek, SeekFrom};
+
+use crate::error::WavError;
+
+// Header size of the RIFF container: "RIFF", size, "WAVE".
+pub const RIFF_HEADER_LEN: usize = 12;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkInfo {
+    pub id: [u8; 4],
+    // offset of the chunk's 8 byte header
+    pub offset: usize,
+    // size as declared in the header, which may be wrong for damaged files
+    pub size: u32,
+}
+
+impl ChunkInfo {
+    pub fn data_start(&self) -> usize {
+        self.offset + 8
+    }
+}
+
+// RF64 and BW64 keep the RIFF layout but put the real sizes in a `ds64`
+// chunk, with u32::MAX in the 32-bit fields.
+pub fn is_rf64(bytes: &[u8]) -> bool {
+    bytes.len() >= 4 && (&bytes[0..4] == b"RF64" || &bytes[0..4] == b"BW64")
+}
+
+pub fn check_riff(bytes: &[u8]) -> io::Result<()> {
+    if is_rf64(bytes) {
+        return Err(WavError::Rf64Unsupported.into());
+    }
+    if bytes.len() < RIFF_HEADER_LEN || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Not a RIFF WAVE file",
+        ));
+    }
+    Ok(())
+}
+
+// Lists the top-level chunks of a RIFF WAVE file. Walking stops at the
+// first chunk whose header does not fit in the file.
+pub fn chunks(bytes: &[u8]) -> io::Result<Vec<ChunkInfo>> {
+    check_riff(bytes)?;
+    let mut chunks = Vec::new();
+    let mut offset = RIFF_HEADER_LEN;
+    while offset + 8 <= bytes.len() {
+        let id = [
+            bytes[offset],
+            bytes[offset + 1],
+            bytes[offset + 2],
+            bytes[offset + 3],
+        ];
+        let size = read_u32(bytes, offset + 4);
+        chunks.push(ChunkInfo { id, offset, size });
+        // chunks are padded to an even length
+        offset += 8 + size as usize + (size as usize & 1);
+    }
+    Ok(chunks)
+}
+
+// Contents of the first top-level chunk with this id.
+pub fn find<'a>(bytes: &'a [u8], id: &[u8; 4]) -> io::Result<Option<&'a [u8]>> {
+    let found = chunks(bytes)?.into_iter().find(|c| &c.id == id);
+    Ok(found.map(|c| {
+        let end = (c.data_start() + c.size as usize).min(bytes.len());
+        &bytes[c.data_start()..end]
+    }))
+}
+
+pub fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        bytes[offset],
+        bytes[offset + 1],
+        bytes[offset + 2],
+        bytes[offset + 3],
+    ])
+}
+
+pub fn write_u32(bytes: &mut [u8], offset: usize, value: u32) {
+    bytes[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+// Rewrites the RIFF size field to match the buffer length.
+pub fn fix_riff_size(bytes: &mut [u8]) {
+    let size = (bytes.len() - 8) as u32;
+    write_u32(bytes, 4, size);
+}
+
+// Encodes a chunk with its header and the pad byte for odd lengths.
+pub fn encode(id: &[u8; 4], contents: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(8 + contents.len() + 1);
+    bytes.extend_from_slice(id);
+    bytes.extend_from_slice(&(contents.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(contents);
+    if contents.len() % 2 == 1 {
+        bytes.push(0);
+    }
+    bytes
+}
+
+// A `LIST`/`INFO` chunk holding a single `ICMT` comment.
+pub fn info_comment(comment: &str) -> Vec<u8> {
+    let mut text = comment.as_bytes().to_vec();
+    text.push(0);
+    let mut info = b"INFO".to_vec();
+    info.extend(encode(b"ICMT", &text));
+    encode(b"LIST", &info)
+}
+
+// Positions `reader` at the contents of the first chunk with this id by
+// seeking past the others, and returns its declared size.
+pub fn seek_chunk<R: Read + Seek>(reader: &mut R, id: &[u8; 4]) -> io::Result<Option<u32>> {
+    let mut riff = [0u8; RIFF_HEADER_LEN];
+    reader.seek(SeekFrom::Start(0))?;
+    reader.read_exact(&mut riff)?;
+    check_riff(&riff)?;
+    let mut header = [0u8; 8];
+    loop {
+        match reader.read_exact(&mut header) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e),
+        }
+        let size = read_u32(&header, 4);
+        if &header[0..4] == id {
+            return Ok(Some(size));
+        }
+        reader.seek(SeekFrom::Current(size as i64 + (size & 1) as i64))?;
+    }
+}
+
+// Reads the contents of the first chunk with this id without loading the
+// rest of the file.
+pub fn read_chunk<R: Read + Seek>(reader: &mut R, id: &[u8; 4]) -> io::Result<Option<Vec<u8>>> {
+    let Some(size) = seek_chunk(reader, id)? else {
+        return Ok(None);
+    };
+    let mut contents = Vec::new();
+    reader.take(size as u64).read_to_end(&mut contents)?;
+    Ok(Some(contents))
+}
@@ -0,0 +1,128 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+// Problems with a file's contents, and refusals that callers may want to
+// tell apart. These travel inside io::Error (kind InvalidData unless noted)
+// so the existing io::Result signatures stay as they are; use wav_error to
+// get them back out.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WavError {
+    CompressedWavUnsupported {
+        format_tag: u16,
+        codec: &'static str,
+    },
+    InvalidSampleRate {
+        rate: u32,
+    },
+    InvalidChannelCount {
+        channels: u16,
+    },
+    // a `fmt ` chunk but no `data` chunk at all, as opposed to an empty one
+    NoDataChunk,
+    // the data chunk ends partway through a frame
+    TruncatedFrame {
+        channels: u16,
+        extra_samples: usize,
+    },
+    // RF64 and BW64 use 64-bit sizes for files over 4 GB
+    Rf64Unsupported,
+    // more channels than BatchOptions::max_channels allows
+    TooManyChannels {
+        channels: u16,
+        max: u16,
+    },
+    // the output read back differs from what was written
+    VerificationFailed {
+        path: PathBuf,
+    },
+    // kind NotFound: Wav::write_existing was given a folder that isn't there
+    OutputDirMissing {
+        dir: PathBuf,
+    },
+    // kind InvalidInput: more files waiting than BatchOptions::max_files
+    TooManyFiles {
+        files: usize,
+        max: usize,
+    },
+    // kind InvalidInput: the output resolves to the input file itself
+    SameInputOutput {
+        output: PathBuf,
+    },
+}
+
+impl fmt::Display for WavError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WavError::CompressedWavUnsupported { format_tag, codec } => write!(
+                f,
+                "{} compressed WAV (format 0x{:04x}) is not supported, only PCM and float",
+                codec, format_tag
+            ),
+            WavError::InvalidSampleRate { rate } => {
+                write!(f, "Invalid sample rate of {} Hz in the header", rate)
+            }
+            WavError::InvalidChannelCount { channels } => {
+                write!(f, "Invalid channel count of {} in the header", channels)
+            }
+            WavError::NoDataChunk => write!(f, "The file has no \"data\" chunk"),
+            WavError::TruncatedFrame {
+                channels,
+                extra_samples,
+            } => write!(
+                f,
+                "Audio ends partway through a frame: {} of {} samples",
+                extra_samples, channels
+            ),
+            WavError::Rf64Unsupported => {
+                write!(f, "RF64/BW64 files over 4 GB are not supported")
+            }
+            WavError::TooManyChannels { channels, max } => {
+                write!(f, "{} channels is more than the limit of {}", channels, max)
+            }
+            WavError::VerificationFailed { path } => {
+                write!(f, "Verification failed for {:?}", path)
+            }
+            WavError::OutputDirMissing { dir } => {
+                write!(f, "Output folder {:?} does not exist", dir)
+            }
+            WavError::TooManyFiles { files, max } => write!(
+                f,
+                "{} files to convert, more than the limit of {}",
+                files, max
+            ),
+            WavError::SameInputOutput { output } => {
+                write!(f, "Output {:?} is the input file itself", output)
+            }
+        }
+    }
+}
+
+impl Error for WavError {}
+
+impl From<WavError> for io::Error {
+    fn from(e: WavError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, e)
+    }
+}
+
+pub fn wav_error(e: &io::Error) -> Option<&WavError> {
+    e.get_ref()?.downcast_ref()
+}
+
+// Names of common non-PCM format tags.
+pub fn codec_name(format_tag: u16) -> Option<&'static str> {
+    Some(match format_tag {
+        0x0002 => "MS ADPCM",
+        0x0006 => "A-law",
+        0x0007 => "µ-law",
+        0x0011 => "IMA ADPCM",
+        0x0022 => "TrueSpeech",
+        0x0031 => "GSM 6.10",
+        0x0050 => "MPEG",
+        0x0055 => "MPEG Layer 3",
+        0x0160 => "WMA",
+        _ => return None,
+    })
+}
@@ -0,0 +1,50 @@
+// G.711 expansion of 8-bit µ-law and A-law samples to 16-bit PCM.
+
+pub const WAV_FORMAT_ALAW: u16 = 0x0006;
+pub const WAV_FORMAT_MULAW: u16 = 0x0007;
+
+pub fn mulaw_to_linear(u: u8) -> i16 {
+    let u = !u;
+    let exponent = (u >> 4) & 0x07;
+    let mantissa = (u & 0x0f) as i16;
+    let magnitude = (((mantissa << 3) + 0x84) << exponent) - 0x84;
+    if u & 0x80 != 0 {
+        -magnitude
+    } else {
+        magnitude
+    }
+}
+
+pub fn alaw_to_linear(a: u8) -> i16 {
+    let a = a ^ 0x55;
+    let exponent = (a >> 4) & 0x07;
+    let mantissa = (a & 0x0f) as i16;
+    let magnitude = if exponent == 0 {
+        (mantissa << 4) + 8
+    } else {
+        ((mantissa << 4) + 0x108) << (exponent - 1)
+    };
+    if a & 0x80 != 0 {
+        magnitude
+    } else {
+        -magnitude
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_known_values() {
+        assert_eq!(mulaw_to_linear(0xff), 0);
+        assert_eq!(mulaw_to_linear(0x7f), 0);
+        assert_eq!(mulaw_to_linear(0x80), 32124);
+        assert_eq!(mulaw_to_linear(0x00), -32124);
+        assert_eq!(mulaw_to_linear(0xf0), 120);
+        assert_eq!(alaw_to_linear(0xd5), 8);
+        assert_eq!(alaw_to_linear(0x55), -8);
+        assert_eq!(alaw_to_linear(0xaa), 32256);
+        assert_eq!(alaw_to_linear(0x2a), -32256);
+    }
+}
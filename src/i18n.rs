@@ -0,0 +1,218 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+
+use eframe::egui;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Lang {
+    #[default]
+    English,
+    Japanese,
+}
+
+impl Lang {
+    pub const ALL: [Lang; 2] = [Lang::English, Lang::Japanese];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Lang::English => "English",
+            Lang::Japanese => "日本語",
+        }
+    }
+
+    // as stored in the settings file
+    fn code(self) -> &'static str {
+        match self {
+            Lang::English => "en",
+            Lang::Japanese => "ja",
+        }
+    }
+}
+
+// The settings file, in the per-user config folder: %APPDATA% on Windows,
+// $XDG_CONFIG_HOME or ~/.config elsewhere.
+pub fn settings_path() -> Option<PathBuf> {
+    let dir = if cfg!(windows) {
+        PathBuf::from(env::var_os("APPDATA")?)
+    } else if let Some(dir) = env::var_os("XDG_CONFIG_HOME") {
+        PathBuf::from(dir)
+    } else {
+        PathBuf::from(env::var_os("HOME")?).join(".config")
+    };
+    Some(dir.join("wav2mono").join("settings.txt"))
+}
+
+// The language saved by save_lang; the default when there is none.
+pub fn load_lang(path: &Path) -> Lang {
+    let settings = fs::read_to_string(path).unwrap_or_default();
+    let code = settings.lines().find_map(|line| line.strip_prefix("lang="));
+    Lang::ALL
+        .into_iter()
+        .find(|lang| Some(lang.code()) == code.map(str::trim))
+        .unwrap_or_default()
+}
+
+pub fn save_lang(path: &Path, lang: Lang) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, format!("lang={}\n", lang.code()))
+}
+
+// Declares Text along with Text::ALL, so a new variant can't be left out
+// of the translation test.
+macro_rules! texts {
+    ($($variant:ident,)*) => {
+        #[derive(Debug, Clone, Copy)]
+        pub enum Text {
+            $($variant,)*
+        }
+
+        impl Text {
+            #[cfg(test)]
+            pub const ALL: &'static [Text] = &[$(Text::$variant,)*];
+        }
+    };
+}
+
+texts! {
+    DropHint,
+    ConvertingToMono,
+    DroppingFiles,
+    DevelopedBy,
+    AddComment,
+    NoWavFiles,
+    KeepLoops,
+    RouteClipped,
+    RouteByRate,
+    ChannelFolders,
+    SkipMultichannel,
+    DecodeG711,
+    Repair,
+    Pause,
+    Resume,
+    Threads,
+    Failed,
+    RetryFailed,
+    PlainProgress,
+    // {done} and {total} are filled in
+    Progress,
+    // appended to Progress, with {failed} filled in
+    ProgressFailed,
+    // {count} is filled in
+    UnsupportedSource,
+    // {count} is filled in
+    Skipped,
+}
+
+impl Text {
+    pub fn get(self, lang: Lang) -> &'static str {
+        match lang {
+            Lang::English => match self {
+                Text::DropHint => "Drag-and-drop files onto the window!",
+                Text::ConvertingToMono => "Converting to mono:",
+                Text::DroppingFiles => "Dropping files:",
+                Text::DevelopedBy => "developed by ",
+                Text::AddComment => "Add a conversion comment to outputs",
+                Text::NoWavFiles => "No WAV files found in the drop",
+                Text::KeepLoops => "Keep sampler loop points",
+                Text::RouteClipped => "Put clipped files in clipped/",
+                Text::RouteByRate => "Sort outputs into folders by sample rate",
+                Text::ChannelFolders => "Folders by channel count, e.g. 4=quad:",
+                Text::SkipMultichannel => "Leave files with 3+ channels alone",
+                Text::DecodeG711 => "Decode µ-law/A-law files to 16-bit PCM",
+                Text::Repair => "Recover audio from files with a broken data size",
+                Text::Pause => "Pause",
+                Text::Resume => "Resume",
+                Text::Threads => "files at once (0 = one per CPU)",
+                Text::Failed => "Could not convert:",
+                Text::RetryFailed => "Retry failed",
+                Text::PlainProgress => "Show progress as text only",
+                Text::Progress => "{done} of {total} files done",
+                Text::ProgressFailed => ", {failed} failed",
+                Text::UnsupportedSource => "{count} dropped item(s) had no file to read (unsupported drop source)",
+                Text::Skipped => "{count} dropped file(s) skipped",
+            },
+            Lang::Japanese => match self {
+                Text::DropHint => "ウィンドウにファイルをドラッグ＆ドロップしてください",
+                Text::ConvertingToMono => "モノラルに変換中:",
+                Text::DroppingFiles => "ドロップするファイル:",
+                Text::DevelopedBy => "開発: ",
+                Text::AddComment => "出力に変換コメントを付ける",
+                Text::NoWavFiles => "ドロップにWAVファイルがありません",
+                Text::KeepLoops => "サンプラーのループポイントを残す",
+                Text::RouteClipped => "クリップしたファイルを clipped/ に出力",
+                Text::RouteByRate => "サンプルレートごとのフォルダに出力",
+                Text::ChannelFolders => "チャンネル数ごとの出力フォルダ (例: 4=quad):",
+                Text::SkipMultichannel => "3チャンネル以上のファイルは変換しない",
+                Text::DecodeG711 => "µ-law/A-law を 16 ビット PCM に展開する",
+                Text::Repair => "データサイズが壊れたファイルの音声を復元する",
+                Text::Pause => "一時停止",
+                Text::Resume => "再開",
+                Text::Threads => "同時に変換するファイル数 (0 = CPU数)",
+                Text::Failed => "変換できなかったファイル:",
+                Text::RetryFailed => "失敗したファイルを再試行",
+                Text::PlainProgress => "進捗をテキストのみで表示",
+                Text::Progress => "{total} 件中 {done} 件完了",
+                Text::ProgressFailed => "、{failed} 件失敗",
+                Text::UnsupportedSource => "{count} 件のドロップは読み込めるファイルがありません (対応していないドロップ元)",
+                Text::Skipped => "{count} 件のファイルをスキップしました",
+            },
+        }
+    }
+}
+
+// The bundled egui fonts have no Japanese glyphs, so borrow one from the OS.
+const JAPANESE_FONTS: &[&str] = &[
+    "C:\\Windows\\Fonts\\meiryo.ttc",
+    "C:\\Windows\\Fonts\\msgothic.ttc",
+    "/System/Library/Fonts/ヒラギノ角ゴシック W3.ttc",
+    "/System/Library/Fonts/Hiragino Sans GB.ttc",
+    "/usr/share/fonts/opentype/noto/NotoSansCJK-Regular.ttc",
+    "/usr/share/fonts/noto-cjk/NotoSansCJK-Regular.ttc",
+];
+
+pub fn install_japanese_font(ctx: &egui::Context) {
+    let Some(font) = JAPANESE_FONTS.iter().find_map(|path| fs::read(path).ok()) else {
+        return;
+    };
+    let mut fonts = egui::FontDefinitions::default();
+    fonts
+        .font_data
+        .insert("japanese".to_owned(), egui::FontData::from_owned(font));
+    for family in fonts.families.values_mut() {
+        family.push("japanese".to_owned());
+    }
+    ctx.set_fonts(fonts);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lang_is_saved() {
+        let path = Path::new("test/settings/settings.txt");
+        let _ = fs::remove_file(path);
+        assert_eq!(load_lang(path), Lang::English);
+        save_lang(path, Lang::Japanese).unwrap();
+        assert_eq!(load_lang(path), Lang::Japanese);
+        save_lang(path, Lang::English).unwrap();
+        assert_eq!(load_lang(path), Lang::English);
+
+        fs::write(path, "lang=xx\n").unwrap();
+        assert_eq!(load_lang(path), Lang::English);
+    }
+
+    #[test]
+    fn test_every_text_is_translated() {
+        for lang in Lang::ALL {
+            for &text in Text::ALL {
+                assert!(!text.get(lang).is_empty(), "{:?} {:?}", lang, text);
+            }
+        }
+    }
+}
@@ -1,134 +1,2690 @@
+mod analysis;
+mod checksum;
+mod chunk;
+mod error;
+mod g711;
+#[cfg(test)]
+mod test_utils;
+
+pub use checksum::crc32;
+pub use error::{codec_name, wav_error, WavError};
+
+pub use analysis::{
+    clip_ratio, clipped_samples, diff_channels, effective_bits, is_mono_reduction_of, metrics_csv,
+    peak_envelope, samples_f32, stereo_metrics, StereoMetrics,
+};
+
+use std::collections::HashSet;
 use std::fs;
 use std::fs::File;
+use std::fs::OpenOptions;
 use std::io;
+use std::io::Cursor;
+use std::io::Read;
+use std::io::Seek;
+use std::io::Write;
 use std::path::Path;
+use std::path::PathBuf;
+use std::sync::mpsc::Sender;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+use std::time::SystemTime;
 
 use wav::bit_depth::BitDepth;
 use wav::header::Header;
+use wav::header::{WAV_FORMAT_IEEE_FLOAT, WAV_FORMAT_PCM};
+
+#[derive(Debug, Clone, Default)]
+pub struct BatchOptions {
+    // canonical paths of the files already converted, one per line;
+    // appended as the batch goes
+    pub state_file: Option<PathBuf>,
+    // only file names matching this glob (`*` and `?`) are converted
+    pub name_filter: Option<String>,
+    // only files last modified after this time are converted
+    pub modified_after: Option<SystemTime>,
+    // re-read each output before it replaces the original
+    pub verify: bool,
+    // expand µ-law and A-law files to 16-bit PCM, see open_wav_decoded,
+    // instead of failing on them
+    pub decode_g711: bool,
+    // recover files whose data chunk size is zero or too large, see
+    // open_wav_repaired
+    pub repair: bool,
+    // extra attempts for a file that fails with a transient I/O error,
+    // waiting retry_backoff before the first and doubling it each time
+    pub retries: u32,
+    pub retry_backoff: Duration,
+    pub multichannel: MultichannelPolicy,
+    pub silence: SilencePolicy,
+    pub symlinks: SymlinkPolicy,
+    pub order: SortOrder,
+    // carry on past a file that fails to convert instead of stopping there;
+    // the batch still returns the first error once every file is tried
+    pub keep_going: bool,
+    // refuse to start when more files than this are waiting to be converted,
+    // in case a much bigger folder was picked than intended
+    pub max_files: Option<usize>,
+    // files with more channels than this fail with TooManyChannels instead
+    // of being converted
+    pub max_channels: Option<u16>,
+    // written once the batch is done, or stops on an error: the path of
+    // every file rewritten to mono, one per line in the order they were
+    // converted, for handing on to another tool. Files that were already
+    // mono are left out.
+    pub output_list: Option<PathBuf>,
+    // progress of the batch, for front ends that draw their own UI
+    pub events: Option<Sender<BatchEvent>>,
+}
+
+// Sent for each file in the order they happen. A file is either skipped,
+// or started and then finished or failed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BatchEvent {
+    Started {
+        path: PathBuf,
+    },
+    Skipped {
+        path: PathBuf,
+    },
+    // elapsed is the wall-clock time spent on the file, retries included;
+    // crc32 is the checksum of the file as written
+    Finished {
+        path: PathBuf,
+        elapsed: Duration,
+        crc32: u32,
+        warnings: Vec<ConversionWarning>,
+    },
+    Failed {
+        path: PathBuf,
+        error: String,
+    },
+}
+
+// What conversion does with files of three or more channels.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MultichannelPolicy {
+    // convert them like stereo files
+    #[default]
+    Convert,
+    // neither rewrite nor copy them
+    LeaveInPlace,
+}
+
+// What conversion does with files whose every channel is silent, which are
+// often empty captures rather than real takes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SilencePolicy {
+    #[default]
+    Convert,
+    LeaveInPlace,
+}
+
+// The order batch conversion goes through the files in. Ties keep the
+// listed order.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SortOrder {
+    // as they come: sorted by path for a folder, line order for a manifest
+    #[default]
+    AsListed,
+    // by file name, whatever folder the file is in
+    Alphabetical,
+    // smallest first
+    BySize,
+    // oldest first
+    ByMtime,
+}
+
+// What batch conversion does with files that are symlinks.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SymlinkPolicy {
+    // convert the file the link points to, keeping the link
+    #[default]
+    Follow,
+    // leave both the link and its target alone
+    LeaveInPlace,
+}
 
 pub fn wav_files_to_mono(dir: &str) -> io::Result<()> {
+    wav_files_to_mono_with(dir, &BatchOptions::default())
+}
+
+pub fn wav_files_to_mono_with(dir: &str, options: &BatchOptions) -> io::Result<()> {
+    convert_files(wav_files_in(Path::new(dir))?, options)
+}
+
+// Converts the files named in a manifest, one path per line, instead of
+// every file in a folder. A line naming a folder stands for the `.wav`
+// files in it. Relative paths are taken from the manifest's folder; blank
+// lines and lines starting with `#` are ignored.
+pub fn wav_files_to_mono_listed(manifest: &Path, options: &BatchOptions) -> io::Result<()> {
+    convert_files(read_manifest(manifest)?, options)
+}
+
+fn read_manifest(manifest: &Path) -> io::Result<Vec<PathBuf>> {
+    let base = manifest.parent().unwrap_or(Path::new(""));
+    let mut files = Vec::new();
+    for line in fs::read_to_string(manifest)?.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let path = base.join(line);
+        if path.is_dir() {
+            files.extend(wav_files_in(&path)?);
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(files)
+}
+
+fn convert_files(files: Vec<PathBuf>, options: &BatchOptions) -> io::Result<()> {
+    let mut rewritten = Vec::new();
+    let result = convert_each(files, options, &mut rewritten);
+    // written even when the batch stops early, listing what got done
+    let listed = match &options.output_list {
+        Some(list) => write_output_list(list, &rewritten),
+        None => Ok(()),
+    };
+    result.and(listed)
+}
+
+fn write_output_list(list: &Path, paths: &[PathBuf]) -> io::Result<()> {
+    let mut list = File::create(list)?;
+    for path in paths {
+        writeln!(list, "{}", path.display())?;
+    }
+    Ok(())
+}
+
+// Converts `files` per `options`, adding each file that was rewritten to
+// `rewritten` as it goes.
+fn convert_each(
+    mut files: Vec<PathBuf>,
+    options: &BatchOptions,
+    rewritten: &mut Vec<PathBuf>,
+) -> io::Result<()> {
+    if let Some(pattern) = &options.name_filter {
+        files.retain(|path| glob_match(pattern, &path.file_name().unwrap().to_string_lossy()));
+    }
+    if let Some(after) = options.modified_after {
+        let mut recent = Vec::new();
+        for path in files {
+            if fs::metadata(&path)?.modified()? > after {
+                recent.push(path);
+            }
+        }
+        files = recent;
+    }
+    match options.order {
+        SortOrder::AsListed => {}
+        SortOrder::Alphabetical => files.sort_by(|a, b| a.file_name().cmp(&b.file_name())),
+        SortOrder::BySize => sort_by_metadata(&mut files, |meta| Ok(meta.len()))?,
+        SortOrder::ByMtime => sort_by_metadata(&mut files, fs::Metadata::modified)?,
+    }
+
+    let done = match &options.state_file {
+        Some(state) => read_state(state)?,
+        None => HashSet::new(),
+    };
+    if let Some(max_files) = options.max_files {
+        let pending = files
+            .iter()
+            .filter(|path| !done.contains(&state_key(path)))
+            .count();
+        if pending > max_files {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                WavError::TooManyFiles {
+                    files: pending,
+                    max: max_files,
+                },
+            ));
+        }
+    }
+    // a receiver that has gone away is not a reason to stop converting
+    let send = |event| {
+        if let Some(events) = &options.events {
+            let _ = events.send(event);
+        }
+    };
+    let mut first_error = None;
+    // records a file's error and says whether the batch carries on
+    let mut fail = |path, e: io::Error| {
+        let error = e.to_string();
+        send(BatchEvent::Failed { path, error });
+        first_error.get_or_insert(e);
+        options.keep_going
+    };
+    for path in files {
+        let key = state_key(&path);
+        if done.contains(&key) {
+            send(BatchEvent::Skipped { path });
+            continue;
+        }
+        match left_in_place(&path, options) {
+            Ok(false) => {}
+            Ok(true) => {
+                send(BatchEvent::Skipped { path });
+                continue;
+            }
+            Err(e) => {
+                if fail(path, e) {
+                    continue;
+                }
+                break;
+            }
+        }
+        send(BatchEvent::Started { path: path.clone() });
+        let start = Instant::now();
+        let converted = check_channel_limit(&path, options.max_channels).and_then(|()| {
+            with_retry(options.retries, options.retry_backoff, || {
+                convert_in_place(&path, options)
+            })
+        });
+        let warnings = match converted {
+            Ok(Some(warnings)) => {
+                rewritten.push(path.clone());
+                warnings
+            }
+            // already mono and left as it was
+            Ok(None) => Vec::new(),
+            Err(e) => {
+                if fail(path, e) {
+                    continue;
+                }
+                break;
+            }
+        };
+        let elapsed = start.elapsed();
+        // the file is converted by now, so it goes in the state file first
+        let recorded = match &options.state_file {
+            Some(state) => append_state(state, &key),
+            None => Ok(()),
+        };
+        let checksum = recorded.and_then(|()| match options.events {
+            Some(_) => fs::read(&path).map(|bytes| Some(crc32(&bytes))),
+            None => Ok(None),
+        });
+        match checksum {
+            Ok(Some(crc32)) => send(BatchEvent::Finished {
+                path,
+                elapsed,
+                crc32,
+                warnings,
+            }),
+            Ok(None) => {}
+            Err(e) => {
+                if fail(path, e) {
+                    continue;
+                }
+                break;
+            }
+        }
+    }
+    match first_error {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+// Whether the policies in `options` say to leave this file alone.
+fn left_in_place(path: &Path, options: &BatchOptions) -> io::Result<bool> {
+    if options.multichannel == MultichannelPolicy::LeaveInPlace
+        && read_header(path)?.channel_count > 2
+    {
+        return Ok(true);
+    }
+    if options.symlinks == SymlinkPolicy::LeaveInPlace
+        && fs::symlink_metadata(path)?.file_type().is_symlink()
+    {
+        return Ok(true);
+    }
+    if options.silence == SilencePolicy::LeaveInPlace {
+        let (_, data) = open_source(path, options)?;
+        if is_silent(&data) {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+fn sort_by_metadata<K, F>(files: &mut Vec<PathBuf>, key: F) -> io::Result<()>
+where
+    K: Ord,
+    F: Fn(&fs::Metadata) -> io::Result<K>,
+{
+    let mut keyed = Vec::new();
+    for path in files.drain(..) {
+        keyed.push((key(&fs::metadata(&path)?)?, path));
+    }
+    keyed.sort_by(|a, b| a.0.cmp(&b.0));
+    files.extend(keyed.into_iter().map(|(_, path)| path));
+    Ok(())
+}
+
+fn check_channel_limit(path: &Path, max: Option<u16>) -> io::Result<()> {
+    let Some(max) = max else {
+        return Ok(());
+    };
+    let channels = read_header(path)?.channel_count;
+    if channels > max {
+        return Err(WavError::TooManyChannels { channels, max }.into());
+    }
+    Ok(())
+}
+
+// Errors that network shares report for a hiccup rather than a bad file.
+fn is_transient(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::Interrupted
+            | io::ErrorKind::TimedOut
+            | io::ErrorKind::WouldBlock
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::NotConnected
+            | io::ErrorKind::BrokenPipe
+    )
+}
+
+fn with_retry<T, F>(retries: u32, backoff: Duration, mut f: F) -> io::Result<T>
+where
+    F: FnMut() -> io::Result<T>,
+{
+    let mut wait = backoff;
+    let mut attempt = 0;
+    loop {
+        match f() {
+            Err(e) if attempt < retries && is_transient(&e) => {
+                thread::sleep(wait);
+                wait *= 2;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+// `.wav` files directly inside `dir`, sorted by path
+fn wav_files_in(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
     for f in fs::read_dir(dir)? {
-        let f = f?;
-        let path = f.path();
+        let path = f?.path();
         if path.extension().unwrap_or_default() == "wav" {
-            wav_file_to_mono(&path)?
+            files.push(path);
         }
     }
-    Ok(())
+    files.sort();
+    Ok(files)
+}
+
+fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // position of the last `*` and the name index it was tried at
+    let mut star = None;
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, n));
+            p += 1;
+        } else if let Some((sp, sn)) = star {
+            p = sp + 1;
+            n = sn + 1;
+            star = Some((sp, sn + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+// Files are recorded by canonical path, as a manifest can list two files
+// with the same name in different folders.
+fn state_key(path: &Path) -> String {
+    let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_owned());
+    path.to_string_lossy().into_owned()
 }
 
+fn append_state(path: &Path, key: &str) -> io::Result<()> {
+    let mut state = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(state, "{}", key)
+}
+
+fn read_state(path: &Path) -> io::Result<HashSet<String>> {
+    match fs::read_to_string(path) {
+        Ok(s) => Ok(s.lines().map(str::to_owned).collect()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(HashSet::new()),
+        Err(e) => Err(e),
+    }
+}
+
+// A symlink is converted through: the file it points to is rewritten and
+// the link itself is kept.
 pub fn wav_file_to_mono(path: &Path) -> io::Result<()> {
-    let (header, data) = open_wav(path)?;
-    let (header, data) = to_mono(header, data)
-        .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "Failed to convert to mono"))?;
-    write_wav(path, header, data)
+    convert_in_place(path, &BatchOptions::default()).map(drop)
+}
+
+// Writes the mono version next to the original and only replaces the
+// original once the written file reads back sample for sample.
+pub fn wav_file_to_mono_verified(path: &Path) -> io::Result<()> {
+    let options = BatchOptions {
+        verify: true,
+        ..Default::default()
+    };
+    convert_in_place(path, &options).map(drop)
+}
+
+// Reads a file to convert, the way the options say to.
+fn open_source(path: &Path, options: &BatchOptions) -> io::Result<(Header, BitDepth)> {
+    if options.decode_g711 {
+        open_wav_decoded(path)
+    } else if options.repair {
+        open_wav_repaired(path).map(|(header, data, _)| (header, data))
+    } else {
+        open_wav(path)
+    }
+}
+
+// Conditions worth telling the user about that still leave a good output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConversionWarning {
+    // channel 0 was silent, so this channel was kept instead
+    SilentChannelSkipped { used: usize },
+    // samples sitting in runs at full scale, see clipped_samples
+    Clipped { samples: usize },
+}
+
+// None when the file was already mono and so left alone.
+fn convert_in_place(
+    path: &Path,
+    options: &BatchOptions,
+) -> io::Result<Option<Vec<ConversionWarning>>> {
+    let path = &fs::canonicalize(path)?;
+    // already mono, rewriting would only produce the same file
+    if read_header(path)?.channel_count == 1 {
+        return Ok(None);
+    }
+    check_writable(path)?;
+    let (header, data) = open_source(path, options)?;
+    let mut warnings = Vec::new();
+    let used = source_channel(&header, &data);
+    if used != 0 {
+        warnings.push(ConversionWarning::SilentChannelSkipped { used });
+    }
+    let clipped = clipped_samples(&header, &data);
+    if clipped > 0 {
+        warnings.push(ConversionWarning::Clipped { samples: clipped });
+    }
+    if data.is_empty() || !is_supported(&header) {
+        return Err(io::Error::other("Failed to convert to mono"));
+    }
+    // what to_mono does, without finding the source channel a second time
+    let (header, data) = extract_channel(header, data, used);
+    if options.verify {
+        let mono = Wav::new(header, data);
+        let tmp = path.with_extension("wav.tmp");
+        mono.write(&tmp)?;
+        replace_verified(&mono, &tmp, path)?;
+    } else {
+        write_wav(path, header, data)?;
+    }
+    Ok(Some(warnings))
+}
+
+// In-place conversion renames a new file over the original, which would
+// quietly succeed for a read-only original on most systems. Refuse first.
+fn check_writable(path: &Path) -> io::Result<()> {
+    if fs::metadata(path)?.permissions().readonly() {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("{:?} is read-only, not converting it in place", path),
+        ));
+    }
+    Ok(())
+}
+
+fn replace_verified(wav: &Wav, tmp: &Path, dest: &Path) -> io::Result<()> {
+    if let Err(e) = wav.verify(tmp) {
+        let _ = fs::remove_file(tmp);
+        return Err(e);
+    }
+    fs::rename(tmp, dest)
 }
 
 pub fn open_wav(path: &Path) -> io::Result<(Header, BitDepth)> {
     let mut input_file = File::open(path)?;
-    wav::read(&mut input_file)
+    // the wav crate never looks at the magic and would read the ds64 chunk
+    // sizes as garbage
+    let mut magic = [0u8; 4];
+    if input_file.read_exact(&mut magic).is_ok() && chunk::is_rf64(&magic) {
+        return Err(WavError::Rf64Unsupported.into());
+    }
+    input_file.rewind()?;
+    let (header, data) = wav::read(&mut input_file).map_err(|e| explain_read_error(path, e))?;
+    check_header(&header)?;
+    check_whole_frames(&header, &data)?;
+    Ok((header, data))
+}
+
+// A capture cut off mid-frame is damaged, not just short; open_wav_repaired
+// is the way to read one anyway.
+fn check_whole_frames(header: &Header, data: &BitDepth) -> io::Result<()> {
+    let samples = match data {
+        BitDepth::Eight(d) => d.len(),
+        BitDepth::Sixteen(d) => d.len(),
+        BitDepth::TwentyFour(d) => d.len(),
+        BitDepth::ThirtyTwoFloat(d) => d.len(),
+        BitDepth::Empty => 0,
+    };
+    let extra_samples = samples % header.channel_count as usize;
+    if extra_samples != 0 {
+        return Err(WavError::TruncatedFrame {
+            channels: header.channel_count,
+            extra_samples,
+        }
+        .into());
+    }
+    Ok(())
+}
+
+// Lowest sample rate taken as real; anything below is a damaged header.
+const MIN_SAMPLE_RATE: u32 = 1000;
+
+fn check_header(header: &Header) -> io::Result<()> {
+    if header.channel_count == 0 {
+        return Err(WavError::InvalidChannelCount {
+            channels: header.channel_count,
+        }
+        .into());
+    }
+    if header.sampling_rate < MIN_SAMPLE_RATE {
+        return Err(WavError::InvalidSampleRate {
+            rate: header.sampling_rate,
+        }
+        .into());
+    }
+    Ok(())
+}
+
+// The wav crate reports every non-PCM file with the same message, so look
+// at the format tag to say which codec it was, and at the chunks to tell a
+// missing `data` chunk apart from other damage.
+fn explain_read_error(path: &Path, e: io::Error) -> io::Error {
+    match read_header(path) {
+        Ok(header) => match codec_name(header.audio_format) {
+            Some(codec) => WavError::CompressedWavUnsupported {
+                format_tag: header.audio_format,
+                codec,
+            }
+            .into(),
+            None => match File::open(path).and_then(|mut f| chunk::seek_chunk(&mut f, b"data")) {
+                Ok(None) => WavError::NoDataChunk.into(),
+                _ => e,
+            },
+        },
+        Err(header_error) if wav_error(&header_error).is_some() => header_error,
+        Err(_) => e,
+    }
+}
+
+// Like open_wav, but 8-bit µ-law and A-law files are expanded to 16-bit
+// PCM instead of being rejected.
+pub fn open_wav_decoded(path: &Path) -> io::Result<(Header, BitDepth)> {
+    let header = read_header(path)?;
+    let expand: fn(u8) -> i16 = match (header.audio_format, header.bits_per_sample) {
+        (g711::WAV_FORMAT_MULAW, 8) => g711::mulaw_to_linear,
+        (g711::WAV_FORMAT_ALAW, 8) => g711::alaw_to_linear,
+        _ => return open_wav(path),
+    };
+    check_header(&header)?;
+    let bytes = fs::read(path)?;
+    let data = chunk::find(&bytes, b"data")?.ok_or(WavError::NoDataChunk)?;
+    let pcm = Header::new(
+        WAV_FORMAT_PCM,
+        header.channel_count,
+        header.sampling_rate,
+        16,
+    );
+    Ok((
+        pcm,
+        BitDepth::Sixteen(data.iter().map(|&b| expand(b)).collect()),
+    ))
+}
+
+// Whether `output` already holds the mono version of `input`: same format,
+// rate, and depth with one channel, a data chunk of one channel's size, and
+// written no earlier than `input` was last changed. Only headers are read,
+// so this is cheap enough to run before converting.
+pub fn is_already_converted(input: &Path, output: &Path) -> io::Result<bool> {
+    if !output.exists() {
+        return Ok(false);
+    }
+    // an input edited since, e.g. a gain change, keeps its size
+    let modified = |path: &Path| fs::metadata(path)?.modified();
+    if modified(output)? < modified(input)? {
+        return Ok(false);
+    }
+    let (source, mono) = (read_header(input)?, read_header(output)?);
+    let expected = Header::new(
+        source.audio_format,
+        1,
+        source.sampling_rate,
+        source.bits_per_sample,
+    );
+    if mono != expected || source.channel_count == 0 {
+        return Ok(false);
+    }
+    let data_size = |path: &Path| chunk::seek_chunk(&mut File::open(path)?, b"data");
+    let frame_size = source.bytes_per_sample.max(1) as u32;
+    let (Some(source_size), Some(mono_size)) = (data_size(input)?, data_size(output)?) else {
+        return Ok(false);
+    };
+    Ok(source_size / frame_size * mono.bytes_per_sample as u32 == mono_size)
+}
+
+// Reads only the `fmt ` chunk, skipping over the audio data.
+pub fn read_header(path: &Path) -> io::Result<Header> {
+    let mut file = File::open(path)?;
+    let fmt = chunk::read_chunk(&mut file, b"fmt ")?
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Missing \"fmt \" chunk"))?;
+    Header::try_from(fmt.as_slice()).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+// Like open_wav, but when the declared size of the `data` chunk is zero or
+// runs past the end of the file (a recorder that crashed mid-write), the
+// size is recomputed from the file length and the audio is read to EOF.
+// The flag reports whether such a repair was made. A trailing partial frame
+// is kept in the data and dropped on conversion.
+pub fn open_wav_repaired(path: &Path) -> io::Result<(Header, BitDepth, bool)> {
+    let mut bytes = fs::read(path)?;
+    let data = chunk::chunks(&bytes)?
+        .into_iter()
+        .find(|c| &c.id == b"data");
+    let mut repaired = false;
+    if let Some(data) = data {
+        let available = bytes.len() - data.data_start();
+        if (data.size == 0 && available > 0) || data.size as usize > available {
+            chunk::write_u32(&mut bytes, data.offset + 4, available as u32);
+            chunk::fix_riff_size(&mut bytes);
+            repaired = true;
+        }
+    }
+    let (header, data) = wav::read(&mut Cursor::new(bytes))?;
+    check_header(&header)?;
+    Ok((header, data, repaired))
 }
 
 pub fn write_wav(path: &Path, header: Header, data: BitDepth) -> io::Result<()> {
-    let mut output_file = File::create(path)?;
-    wav::write(header, &data, &mut output_file)
+    write_atomic(path, |output_file| wav::write(header, &data, output_file))
+}
+
+// Writes to a temporary file in the destination directory and renames it
+// into place once complete, so `path` never holds a partially written file.
+fn write_atomic<F>(path: &Path, write: F) -> io::Result<()>
+where
+    F: FnOnce(&mut File) -> io::Result<()>,
+{
+    let mut tmp_name = std::ffi::OsString::from(".");
+    tmp_name.push(path.file_name().unwrap_or_default());
+    tmp_name.push(".tmp");
+    let tmp = path.with_file_name(tmp_name);
+
+    let result = File::create(&tmp).and_then(|mut file| {
+        write(&mut file)?;
+        file.sync_all()
+    });
+    match result {
+        Ok(()) => fs::rename(&tmp, path),
+        Err(e) => {
+            let _ = fs::remove_file(&tmp);
+            Err(e)
+        }
+    }
+}
+
+// wav only writes `fmt ` and `data`, so extra chunks are appended to the
+// encoded file afterwards. Each entry is a complete encoded chunk.
+pub fn write_wav_with_chunks(
+    path: &Path,
+    header: Header,
+    data: &BitDepth,
+    chunks: &[Vec<u8>],
+) -> io::Result<()> {
+    let mut bytes = Cursor::new(Vec::new());
+    wav::write(header, data, &mut bytes)?;
+    let mut bytes = bytes.into_inner();
+    for c in chunks {
+        bytes.extend_from_slice(c);
+    }
+    chunk::fix_riff_size(&mut bytes);
+    write_atomic(path, |file| file.write_all(&bytes))
+}
+
+// `<input dir>/mono/<file name>`. A bare file name has an empty parent,
+// so it resolves against the current directory instead.
+pub fn mono_output_path(input: &Path) -> Option<PathBuf> {
+    output_path(input, "mono")
+}
+
+// `<input dir>/<folder>/<file name>`, resolved like mono_output_path.
+pub fn output_path(input: &Path, folder: &str) -> Option<PathBuf> {
+    let file_name = input.file_name()?;
+    let dir = match input.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    Some(dir.join(folder).join(file_name))
+}
+
+// Refuses an output that resolves to the input itself, e.g. a folder of
+// "." or a symlink back to the source directory.
+pub fn check_distinct(input: &Path, output: &Path) -> io::Result<()> {
+    let same = match (input.canonicalize(), output.canonicalize()) {
+        (Ok(input), Ok(output)) => input == output,
+        // an output that does not exist yet cannot be the input
+        _ => false,
+    };
+    if same {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            WavError::SameInputOutput {
+                output: output.to_owned(),
+            },
+        ));
+    }
+    Ok(())
+}
+
+// `<input dir>/split/<stem>_ch<channel>.wav`, for split_channels outputs.
+pub fn channel_output_path(input: &Path, channel: u16) -> Option<PathBuf> {
+    // built from the OsStr so names that are not UTF-8 survive
+    let mut name = input.file_stem()?.to_os_string();
+    name.push(format!("_ch{}.wav", channel));
+    Some(output_path(input, "split")?.with_file_name(name))
+}
+
+pub fn conversion_comment() -> String {
+    format!(
+        "downmixed to mono by wav2mono ver{}",
+        env!("CARGO_PKG_VERSION")
+    )
+}
+
+// (audio format tag, bits per sample) pairs that can be read and converted
+const SUPPORTED_FORMATS: &[(u16, u16)] = &[
+    (WAV_FORMAT_PCM, 8),
+    (WAV_FORMAT_PCM, 16),
+    (WAV_FORMAT_PCM, 24),
+    (WAV_FORMAT_IEEE_FLOAT, 32),
+];
+
+pub fn supported_formats() -> &'static [(u16, u16)] {
+    SUPPORTED_FORMATS
+}
+
+// A header with no channels has no frames to work with, so it is never
+// supported, whatever its format.
+pub fn is_supported(header: &Header) -> bool {
+    header.channel_count > 0
+        && SUPPORTED_FORMATS.contains(&(header.audio_format, header.bits_per_sample))
 }
 
 pub fn to_mono(header: Header, data: BitDepth) -> Option<(Header, BitDepth)> {
-    if data.is_empty() {
+    if data.is_empty() || !is_supported(&header) {
         None
     } else {
-        let channel_count = header.channel_count;
-        let new_header = Header::new(
-            wav::header::WAV_FORMAT_PCM,
-            1,
-            header.sampling_rate,
-            header.bits_per_sample,
-        );
-        let new_data = match data {
-            BitDepth::Eight(d) => BitDepth::Eight(to_mono_data(d.clone(), channel_count)),
-            BitDepth::Sixteen(d) => BitDepth::Sixteen(to_mono_data(d.clone(), channel_count)),
-            BitDepth::TwentyFour(d) => BitDepth::TwentyFour(to_mono_data(d.clone(), channel_count)),
-            BitDepth::ThirtyTwoFloat(d) => {
-                BitDepth::ThirtyTwoFloat(to_mono_data(d.clone(), channel_count))
-            }
-            _ => unreachable!(),
+        let channel = source_channel(&header, &data);
+        Some(extract_channel(header, data, channel))
+    }
+}
+
+// Each selected channel as its own mono file, in the order given.
+pub fn split_channels(
+    header: Header,
+    data: &BitDepth,
+    selection: &[u16],
+) -> io::Result<Vec<(Header, BitDepth)>> {
+    if data.is_empty() || !is_supported(&header) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Nothing to split",
+        ));
+    }
+    if let Some(channel) = selection.iter().find(|&&c| c >= header.channel_count) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "Channel {} does not exist in a {} channel file",
+                channel, header.channel_count
+            ),
+        ));
+    }
+    Ok(selection
+        .iter()
+        .map(|&channel| extract_channel(header, data.clone(), channel as usize))
+        .collect())
+}
+
+// Interleaves mono files back into one multichannel file, the inverse of
+// split_channels. All inputs must share a header; a longer input is cut
+// to the shortest.
+pub fn recombine(monos: &[(Header, BitDepth)]) -> io::Result<(Header, BitDepth)> {
+    let mismatch = || io::Error::new(io::ErrorKind::InvalidInput, "Inputs do not share a format");
+    let Some(&(first, _)) = monos.first() else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Nothing to recombine",
+        ));
+    };
+    if first.channel_count != 1 || monos.iter().any(|(h, _)| *h != first) {
+        return Err(mismatch());
+    }
+    fn interleave<T: Copy>(
+        monos: &[(Header, BitDepth)],
+        get: fn(&BitDepth) -> Option<&Vec<T>>,
+    ) -> Option<Vec<T>> {
+        let channels: Vec<&Vec<T>> = monos.iter().map(|(_, d)| get(d)).collect::<Option<_>>()?;
+        let len = channels.iter().map(|c| c.len()).min().unwrap_or(0);
+        Some(
+            (0..len)
+                .flat_map(|i| channels.iter().map(move |c| c[i]))
+                .collect(),
+        )
+    }
+    let data = match &monos[0].1 {
+        BitDepth::Eight(_) => interleave(monos, BitDepth::as_eight).map(BitDepth::Eight),
+        BitDepth::Sixteen(_) => interleave(monos, BitDepth::as_sixteen).map(BitDepth::Sixteen),
+        BitDepth::TwentyFour(_) => {
+            interleave(monos, BitDepth::as_twenty_four).map(BitDepth::TwentyFour)
+        }
+        BitDepth::ThirtyTwoFloat(_) => {
+            interleave(monos, BitDepth::as_thirty_two_float).map(BitDepth::ThirtyTwoFloat)
+        }
+        BitDepth::Empty => None,
+    }
+    .ok_or_else(mismatch)?;
+    let header = Header::new(
+        first.audio_format,
+        monos.len() as u16,
+        first.sampling_rate,
+        first.bits_per_sample,
+    );
+    Ok((header, data))
+}
+
+// All files converted to mono and joined end to end into one review track,
+// with `gap` of silence between them. They must share a format, rate and
+// bit depth once in mono.
+pub fn concat_mono(paths: &[PathBuf], gap: Duration) -> io::Result<Wav> {
+    let mut joined: Option<(Header, BitDepth)> = None;
+    for path in paths {
+        let (header, data) = open_wav(path)?;
+        let (header, data) = to_mono(header, data)
+            .ok_or_else(|| io::Error::other(format!("Failed to convert {:?} to mono", path)))?;
+        let Some((first, all)) = &mut joined else {
+            joined = Some((header, data));
+            continue;
         };
-        Some((new_header, new_data))
+        if header != *first {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{:?} does not match the format of the first file", path),
+            ));
+        }
+        let gap = (gap.as_secs_f64() * header.sampling_rate as f64).round() as usize;
+        fn append<T: Clone>(all: &mut Vec<T>, silence: T, gap: usize, next: Vec<T>) {
+            all.extend(std::iter::repeat_n(silence, gap));
+            all.extend(next);
+        }
+        match (all, data) {
+            (BitDepth::Eight(a), BitDepth::Eight(b)) => append(a, 128, gap, b),
+            (BitDepth::Sixteen(a), BitDepth::Sixteen(b)) => append(a, 0, gap, b),
+            (BitDepth::TwentyFour(a), BitDepth::TwentyFour(b)) => append(a, 0, gap, b),
+            (BitDepth::ThirtyTwoFloat(a), BitDepth::ThirtyTwoFloat(b)) => append(a, 0.0, gap, b),
+            _ => unreachable!("same header, same sample type"),
+        }
     }
+    let (header, data) =
+        joined.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "No files to join"))?;
+    Ok(Wav::new(header, data))
+}
+
+fn extract_channel(header: Header, data: BitDepth, channel: usize) -> (Header, BitDepth) {
+    let channel_count = header.channel_count;
+    let new_header = Header::new(
+        header.audio_format,
+        1,
+        header.sampling_rate,
+        header.bits_per_sample,
+    );
+    let new_data = match data {
+        BitDepth::Eight(d) => BitDepth::Eight(to_mono_data(d, channel_count, channel)),
+        BitDepth::Sixteen(d) => BitDepth::Sixteen(to_mono_data(d, channel_count, channel)),
+        BitDepth::TwentyFour(d) => BitDepth::TwentyFour(to_mono_data(d, channel_count, channel)),
+        BitDepth::ThirtyTwoFloat(d) => {
+            BitDepth::ThirtyTwoFloat(to_mono_data(d, channel_count, channel))
+        }
+        _ => unreachable!(),
+    };
+    (new_header, new_data)
 }
 
-fn to_mono_data<Int>(data: Vec<Int>, channels_count: u16) -> Vec<Int>
+fn to_mono_data<Int>(data: Vec<Int>, channels_count: u16, channel: usize) -> Vec<Int>
 where
     Int: Clone,
 {
-    data.chunks(channels_count as usize)
-        .map(|chunk| chunk[0].clone())
+    frames(&data, channels_count)
+        .map(|frame| frame[channel].clone())
         .collect()
 }
 
-pub struct Wav {
-    header: Header,
-    data: BitDepth,
+// Groups interleaved samples into frames of `channel_count` samples.
+// A trailing partial frame is dropped.
+pub fn frames<T>(data: &[T], channel_count: u16) -> std::slice::ChunksExact<'_, T> {
+    data.chunks_exact(channel_count as usize)
 }
 
-impl Wav {
-    pub fn new(header: Header, data: BitDepth) -> Self {
-        Wav { header, data }
+// a channel whose peak stays below -60 dBFS is treated as silent
+const SILENCE_PEAK: f32 = 0.001;
+
+pub trait Sample: Copy {
+    // sample value scaled to -1.0..1.0
+    fn to_f32(self) -> f32;
+    // inverse of to_f32, clamping integer formats to their range
+    fn from_f32(value: f32) -> Self;
+}
+
+impl Sample for u8 {
+    fn to_f32(self) -> f32 {
+        (self as f32 - 128.0) / 128.0
     }
 
-    pub fn open(path: &Path) -> Self {
-        let (h, d) = open_wav(path).unwrap_or_else(|_| panic!("Can't open {:?}", path));
-        Wav::new(h, d)
+    fn from_f32(value: f32) -> Self {
+        (value * 128.0 + 128.0).round().clamp(0.0, 255.0) as u8
     }
+}
 
-    pub fn write(&self, path: &Path) -> io::Result<()> {
-        //create directory if missing
-        let dir = path.parent().unwrap();
-        if !dir.exists() {
-            fs::create_dir_all(dir)?;
-        }
-        //write wav file
-        write_wav(path, self.header, self.data.clone())
+impl Sample for i16 {
+    fn to_f32(self) -> f32 {
+        self as f32 / 32768.0
     }
 
-    pub fn to_mono(&mut self) -> &mut Wav {
-        let (h, d) = to_mono(self.header, self.data.clone()).unwrap();
-        self.header = h;
-        self.data = d;
-        self
+    fn from_f32(value: f32) -> Self {
+        (value * 32768.0).round().clamp(-32768.0, 32767.0) as i16
     }
 }
 
-//test
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::path::Path;
+// wav stores 24-bit samples in the upper three bytes of an i32
+impl Sample for i32 {
+    fn to_f32(self) -> f32 {
+        self as f32 / 2147483648.0
+    }
 
-    //test open wave file
-    #[test]
-    fn test_open_wav() {
-        let (header, data) = open_wav(Path::new("test/test.wav")).unwrap();
-        println!("{:?}", header);
-        println!("{}", data.is_eight());
-        assert_eq!(header.channel_count, 2);
-        assert!(data.is_sixteen());
+    fn from_f32(value: f32) -> Self {
+        ((value * 8388608.0).round().clamp(-8388608.0, 8388607.0) as i32) << 8
     }
+}
 
-    #[test]
-    fn test_path() {
-        let path = Path::new("test/mono/test.wav");
-        println!("Path is: {:?}", path);
-        assert!(path.is_relative());
-        assert!(path.exists());
-        assert_eq!(path.parent().unwrap().to_str().unwrap(), "test/mono");
+impl Sample for f32 {
+    fn to_f32(self) -> f32 {
+        self
     }
 
-    #[test]
-    fn test_wav_to_mono() {
-        let mut wav = Wav::open(Path::new("test/test.wav"));
-        wav.to_mono();
-        assert!(wav.write(Path::new("test/mono/test.wav")).is_ok());
+    fn from_f32(value: f32) -> Self {
+        value
+    }
+}
+
+// Fields to replace in an output header. Changing the format or bit depth
+// converts the samples; the sample rate is only retagged, not resampled.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HeaderOverride {
+    pub audio_format: Option<u16>,
+    pub bits_per_sample: Option<u16>,
+    pub sampling_rate: Option<u32>,
+}
+
+pub fn apply_header_override(
+    header: Header,
+    data: &BitDepth,
+    header_override: &HeaderOverride,
+) -> io::Result<(Header, BitDepth)> {
+    let new_header = Header::new(
+        header_override.audio_format.unwrap_or(header.audio_format),
+        header.channel_count,
+        header_override
+            .sampling_rate
+            .unwrap_or(header.sampling_rate),
+        header_override
+            .bits_per_sample
+            .unwrap_or(header.bits_per_sample),
+    );
+    if !is_supported(&new_header) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "Unsupported output format {} with {} bits",
+                new_header.audio_format, new_header.bits_per_sample
+            ),
+        ));
+    }
+    // open_wav would refuse to read the result back
+    if new_header.sampling_rate < MIN_SAMPLE_RATE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "Output sample rate of {} Hz is below the minimum of {} Hz",
+                new_header.sampling_rate, MIN_SAMPLE_RATE
+            ),
+        ));
+    }
+    let samples = samples_f32(data);
+    let new_data = match (new_header.audio_format, new_header.bits_per_sample) {
+        (WAV_FORMAT_PCM, 8) => BitDepth::Eight(from_samples(&samples)),
+        (WAV_FORMAT_PCM, 16) => BitDepth::Sixteen(from_samples(&samples)),
+        (WAV_FORMAT_PCM, 24) => BitDepth::TwentyFour(from_samples(&samples)),
+        _ => BitDepth::ThirtyTwoFloat(samples),
+    };
+    Ok((new_header, new_data))
+}
+
+fn from_samples<T: Sample>(samples: &[f32]) -> Vec<T> {
+    samples.iter().map(|&v| T::from_f32(v)).collect()
+}
+
+// Gain applied to the sum of all channels by to_mono_mixed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DownmixGain {
+    // sum divided by the channel count; keeps the level of correlated content
+    Mean,
+    // plain sum; keeps the energy of uncorrelated content but may clip
+    Sum,
+    // sum at -3 dB, between the two
+    MinusThreeDb,
+}
+
+impl DownmixGain {
+    fn factor(self, channel_count: u16) -> f64 {
+        match self {
+            DownmixGain::Mean => 1.0 / channel_count as f64,
+            DownmixGain::Sum => 1.0,
+            DownmixGain::MinusThreeDb => 10f64.powf(-3.0 / 20.0),
+        }
+    }
+}
+
+// Mixes all channels into one instead of keeping a single channel.
+// Integer output is clamped to the format's range.
+pub fn to_mono_mixed(
+    header: Header,
+    data: BitDepth,
+    gain: DownmixGain,
+) -> Option<(Header, BitDepth)> {
+    to_mono_mixed_lfe(header, data, gain, 1.0)
+}
+
+// Position of the LFE channel in 5.1 and 7.1 files, which WAV orders
+// FL, FR, FC, LFE, then the surrounds. The wav crate drops the channel
+// mask, so this is all there is to go on.
+pub fn lfe_channel(channel_count: u16) -> Option<usize> {
+    match channel_count {
+        6 | 8 => Some(3),
+        _ => None,
+    }
+}
+
+// Like to_mono_mixed, with the LFE channel scaled by `lfe_gain` before
+// mixing; 0.0 leaves it out.
+pub fn to_mono_mixed_lfe(
+    header: Header,
+    data: BitDepth,
+    gain: DownmixGain,
+    lfe_gain: f32,
+) -> Option<(Header, BitDepth)> {
+    if data.is_empty() || !is_supported(&header) {
+        return None;
+    }
+    let factor = gain.factor(header.channel_count);
+    let mut weights = vec![factor; header.channel_count as usize];
+    if let Some(lfe) = lfe_channel(header.channel_count) {
+        weights[lfe] *= lfe_gain as f64;
+    }
+    Some(mix_weighted(header, data, &weights))
+}
+
+// Mixes with one gain per channel, e.g. [0.7, 0.3] for a main and a spot
+// mic. Fails when the number of weights doesn't match the channel count.
+pub fn to_mono_weighted(
+    header: Header,
+    data: BitDepth,
+    weights: &[f32],
+) -> io::Result<(Header, BitDepth)> {
+    if data.is_empty() || !is_supported(&header) {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "Nothing to mix"));
+    }
+    if weights.len() != header.channel_count as usize {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "{} weights given for a {} channel file",
+                weights.len(),
+                header.channel_count
+            ),
+        ));
+    }
+    let weights: Vec<f64> = weights.iter().map(|&w| w as f64).collect();
+    Ok(mix_weighted(header, data, &weights))
+}
+
+fn mix_weighted(header: Header, data: BitDepth, weights: &[f64]) -> (Header, BitDepth) {
+    let new_header = Header::new(
+        header.audio_format,
+        1,
+        header.sampling_rate,
+        header.bits_per_sample,
+    );
+    let new_data = match data {
+        BitDepth::Eight(d) => BitDepth::Eight(mix_data(&d, weights)),
+        BitDepth::Sixteen(d) => BitDepth::Sixteen(mix_data(&d, weights)),
+        BitDepth::TwentyFour(d) => BitDepth::TwentyFour(mix_data(&d, weights)),
+        BitDepth::ThirtyTwoFloat(d) => BitDepth::ThirtyTwoFloat(mix_data(&d, weights)),
+        BitDepth::Empty => unreachable!(),
+    };
+    (new_header, new_data)
+}
+
+// One weight per channel.
+fn mix_data<T: Sample>(data: &[T], weights: &[f64]) -> Vec<T> {
+    frames(data, weights.len() as u16)
+        .map(|frame| {
+            let sum: f64 = frame
+                .iter()
+                .zip(weights)
+                .map(|(s, w)| s.to_f32() as f64 * w)
+                .sum();
+            T::from_f32(sum as f32)
+        })
+        .collect()
+}
+
+fn channel_peaks<T: Sample>(data: &[T], channel_count: u16) -> Vec<f32> {
+    let mut peaks = vec![0.0f32; channel_count as usize];
+    for frame in frames(data, channel_count) {
+        for (peak, s) in peaks.iter_mut().zip(frame) {
+            *peak = peak.max(s.to_f32().abs());
+        }
+    }
+    peaks
+}
+
+// Whether every channel stays below the silence threshold.
+pub fn is_silent(data: &BitDepth) -> bool {
+    samples_f32(data).iter().all(|s| s.abs() < SILENCE_PEAK)
+}
+
+// Channel kept by to_mono: channel 0, unless it is silent while another
+// channel has audio, in which case the loudest channel is used instead.
+pub fn source_channel(header: &Header, data: &BitDepth) -> usize {
+    if header.channel_count == 0 {
+        return 0;
+    }
+    let peaks = match data {
+        BitDepth::Eight(d) => channel_peaks(d, header.channel_count),
+        BitDepth::Sixteen(d) => channel_peaks(d, header.channel_count),
+        BitDepth::TwentyFour(d) => channel_peaks(d, header.channel_count),
+        BitDepth::ThirtyTwoFloat(d) => channel_peaks(d, header.channel_count),
+        BitDepth::Empty => return 0,
+    };
+    match peaks.first() {
+        Some(&peak) if peak < SILENCE_PEAK => {
+            let (loudest, best) =
+                peaks.iter().enumerate().fold(
+                    (0, peak),
+                    |best, (i, &p)| if p > best.1 { (i, p) } else { best },
+                );
+            // every channel silent: nothing to fall back to
+            if best < SILENCE_PEAK {
+                0
+            } else {
+                loudest
+            }
+        }
+        _ => 0,
+    }
+}
+
+pub struct Wav {
+    header: Header,
+    data: BitDepth,
+    // extra encoded chunks written after `data`
+    chunks: Vec<Vec<u8>>,
+}
+
+impl Wav {
+    pub fn new(header: Header, data: BitDepth) -> Self {
+        Wav {
+            header,
+            data,
+            chunks: Vec::new(),
+        }
+    }
+
+    pub fn open(path: &Path) -> Self {
+        let (h, d) = open_wav(path).unwrap_or_else(|_| panic!("Can't open {:?}", path));
+        Wav::new(h, d)
+    }
+
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        //create directory if missing
+        if let Some(dir) = path.parent() {
+            if !dir.exists() {
+                fs::create_dir_all(dir)?;
+            }
+        }
+        self.write_existing(path)
+    }
+
+    // Like write, but the output folder must already exist; for setups
+    // where only pre-made destinations are allowed.
+    pub fn write_existing(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            if !dir.is_dir() {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    WavError::OutputDirMissing {
+                        dir: dir.to_owned(),
+                    },
+                ));
+            }
+        }
+        //write wav file
+        if self.chunks.is_empty() {
+            write_wav(path, self.header, self.data.clone())
+        } else {
+            write_wav_with_chunks(path, self.header, &self.data, &self.chunks)
+        }
+    }
+
+    pub fn to_mono_mixed(&mut self, gain: DownmixGain) -> &mut Wav {
+        let (h, d) = to_mono_mixed(self.header, self.data.clone(), gain).unwrap();
+        self.header = h;
+        self.data = d;
+        self
+    }
+
+    pub fn to_mono_mixed_lfe(&mut self, gain: DownmixGain, lfe_gain: f32) -> &mut Wav {
+        let (h, d) = to_mono_mixed_lfe(self.header, self.data.clone(), gain, lfe_gain).unwrap();
+        self.header = h;
+        self.data = d;
+        self
+    }
+
+    pub fn to_mono_weighted(&mut self, weights: &[f32]) -> io::Result<&mut Wav> {
+        let (h, d) = to_mono_weighted(self.header, self.data.clone(), weights)?;
+        self.header = h;
+        self.data = d;
+        Ok(self)
+    }
+
+    pub fn with_header_override(
+        &mut self,
+        header_override: &HeaderOverride,
+    ) -> io::Result<&mut Wav> {
+        let (h, d) = apply_header_override(self.header, &self.data, header_override)?;
+        self.header = h;
+        self.data = d;
+        Ok(self)
+    }
+
+    pub fn split_channels(&self, selection: &[u16]) -> io::Result<Vec<Wav>> {
+        let split = split_channels(self.header, &self.data, selection)?;
+        Ok(split.into_iter().map(|(h, d)| Wav::new(h, d)).collect())
+    }
+
+    pub fn clip_ratio(&self) -> f32 {
+        clip_ratio(&self.header, &self.data)
+    }
+
+    // Checks that the file at `path` holds exactly this header and audio.
+    pub fn verify(&self, path: &Path) -> io::Result<()> {
+        let (header, data) = open_wav(path)?;
+        if header != self.header || data != self.data {
+            return Err(WavError::VerificationFailed {
+                path: path.to_owned(),
+            }
+            .into());
+        }
+        Ok(())
+    }
+
+    // Copies the `smpl` chunk of `source`, if it has one. Its loop points
+    // count sample frames, so they stay valid for the mono output.
+    pub fn keep_sample_chunk(&mut self, source: &Path) -> io::Result<&mut Wav> {
+        let bytes = fs::read(source)?;
+        if let Some(smpl) = chunk::find(&bytes, b"smpl")? {
+            self.chunks.push(chunk::encode(b"smpl", smpl));
+        }
+        Ok(self)
+    }
+
+    // Adds an INFO/ICMT comment to the written file.
+    pub fn with_comment(&mut self, comment: &str) -> &mut Wav {
+        self.chunks.push(chunk::info_comment(comment));
+        self
+    }
+
+    pub fn to_mono(&mut self) -> &mut Wav {
+        let (h, d) = to_mono(self.header, self.data.clone()).unwrap();
+        self.header = h;
+        self.data = d;
+        self
+    }
+}
+
+//test
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+    use crate::test_utils::{write_stereo, write_wav_file};
+    use std::path::Path;
+
+    //test open wave file
+    #[test]
+    fn test_open_wav() {
+        let (header, data) = open_wav(Path::new("test/test.wav")).unwrap();
+        println!("{:?}", header);
+        println!("{}", data.is_eight());
+        assert_eq!(header.channel_count, 2);
+        assert!(data.is_sixteen());
+    }
+
+    #[test]
+    fn test_path() {
+        let path = Path::new("test/mono/test.wav");
+        println!("Path is: {:?}", path);
+        assert!(path.is_relative());
+        assert!(path.exists());
+        assert_eq!(path.parent().unwrap().to_str().unwrap(), "test/mono");
+    }
+
+    #[test]
+    fn test_wav_to_mono() {
+        let mut wav = Wav::open(Path::new("test/test.wav"));
+        wav.to_mono();
+        assert!(wav.write(Path::new("test/mono/test.wav")).is_ok());
+    }
+
+    #[test]
+    fn test_resume_from_state_file() {
+        let dir = Path::new("test/resume");
+        let _ = fs::remove_dir_all(dir);
+        for name in ["a.wav", "b.wav", "d.wav"] {
+            write_stereo(&dir.join(name));
+        }
+        // not a wav, so the first run stops here after a.wav and b.wav
+        fs::write(dir.join("c.wav"), b"broken").unwrap();
+        let options = BatchOptions {
+            state_file: Some(dir.join("state.txt")),
+            ..BatchOptions::default()
+        };
+
+        let lines = |names: &[&str]| {
+            let dir = fs::canonicalize(dir).unwrap();
+            names
+                .iter()
+                .map(|name| format!("{}\n", dir.join(name).display()))
+                .collect::<String>()
+        };
+        assert!(wav_files_to_mono_with("test/resume", &options).is_err());
+        let state = fs::read_to_string(dir.join("state.txt")).unwrap();
+        assert_eq!(state, lines(&["a.wav", "b.wav"]));
+
+        // a.wav would fail if it were processed again
+        fs::write(dir.join("a.wav"), b"broken").unwrap();
+        write_stereo(&dir.join("c.wav"));
+        wav_files_to_mono_with("test/resume", &options).unwrap();
+        let state = fs::read_to_string(dir.join("state.txt")).unwrap();
+        assert_eq!(state, lines(&["a.wav", "b.wav", "c.wav", "d.wav"]));
+        let (header, _) = open_wav(&dir.join("d.wav")).unwrap();
+        assert_eq!(header.channel_count, 1);
+    }
+
+    #[test]
+    fn test_silent_right_keeps_left_content() {
+        let path = Path::new("test/padded/padded.wav");
+        let header = Header::new(WAV_FORMAT_PCM, 2, 44100, 16);
+        write_wav_file(
+            path,
+            header,
+            &BitDepth::Sixteen(vec![500, 0, -700, 0, 900, 0]),
+        );
+        wav_file_to_mono(path).unwrap();
+
+        let (header, data) = open_wav(path).unwrap();
+        assert_eq!(header.channel_count, 1);
+        assert_eq!(data, BitDepth::Sixteen(vec![500, -700, 900]));
+    }
+
+    #[test]
+    fn test_frames() {
+        let data = [1, 2, 3, 4, 5, 6, 7];
+        let grouped: Vec<&[i32]> = frames(&data, 3).collect();
+        assert_eq!(grouped, vec![&[1, 2, 3][..], &[4, 5, 6][..]]);
+        assert_eq!(frames(&data, 2).remainder(), &[7]);
+
+        let header = Header::new(WAV_FORMAT_PCM, 2, 44100, 16);
+        let (_, data) = to_mono(header, BitDepth::Sixteen(vec![100, 200, 300, 400, 500])).unwrap();
+        assert_eq!(data, BitDepth::Sixteen(vec![100, 300]));
+    }
+
+    #[test]
+    fn test_silent_left_falls_back_to_right() {
+        let header = Header::new(WAV_FORMAT_PCM, 2, 44100, 16);
+        let data = BitDepth::Sixteen(vec![0, 1000, 0, -2000, 0, 3000]);
+        assert_eq!(source_channel(&header, &data), 1);
+        let (_, data) = to_mono(header, data).unwrap();
+        assert_eq!(data, BitDepth::Sixteen(vec![1000, -2000, 3000]));
+
+        // both channels below the silence threshold keep channel 0
+        let quiet = BitDepth::Sixteen(vec![0, 10, 0, -20, 0, 30]);
+        assert_eq!(source_channel(&header, &quiet), 0);
+    }
+
+    #[test]
+    fn test_mono_output_path() {
+        assert_eq!(
+            mono_output_path(Path::new("foo.wav")).unwrap(),
+            Path::new(".").join("mono").join("foo.wav")
+        );
+        assert_eq!(
+            mono_output_path(Path::new("test/test.wav")).unwrap(),
+            Path::new("test/mono/test.wav")
+        );
+        assert!(mono_output_path(Path::new("/")).is_none());
+        assert_eq!(
+            output_path(Path::new("test/test.wav"), "clipped").unwrap(),
+            Path::new("test/clipped/test.wav")
+        );
+    }
+
+    #[test]
+    fn test_verify_keeps_source_on_mismatch() {
+        let dir = Path::new("test/verify");
+        let source = dir.join("source.wav");
+        write_stereo(&source);
+        let original = fs::read(&source).unwrap();
+
+        let (header, data) = open_wav(&source).unwrap();
+        let (header, data) = to_mono(header, data).unwrap();
+        let mono = Wav::new(header, data);
+        let tmp = dir.join("source.wav.tmp");
+        mono.write(&tmp).unwrap();
+        // flip a sample in the written output
+        let mut bytes = fs::read(&tmp).unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0x40;
+        fs::write(&tmp, bytes).unwrap();
+
+        let e = replace_verified(&mono, &tmp, &source).unwrap_err();
+        assert_eq!(
+            wav_error(&e),
+            Some(&WavError::VerificationFailed { path: tmp.clone() })
+        );
+        assert_eq!(fs::read(&source).unwrap(), original);
+        assert!(!tmp.exists());
+
+        wav_file_to_mono_verified(&source).unwrap();
+        let (header, _) = open_wav(&source).unwrap();
+        assert_eq!(header.channel_count, 1);
+    }
+
+    #[test]
+    fn test_failed_write_leaves_no_partial_file() {
+        let dir = Path::new("test/atomic");
+        let _ = fs::remove_dir_all(dir);
+        fs::create_dir_all(dir).unwrap();
+        let path = dir.join("out.wav");
+
+        let result = write_atomic(&path, |file| {
+            file.write_all(b"RIFF")?;
+            Err(io::Error::other("disconnected"))
+        });
+        assert!(result.is_err());
+        assert_eq!(fs::read_dir(dir).unwrap().count(), 0);
+
+        write_stereo(&path);
+        let before = fs::read(&path).unwrap();
+        let result = write_atomic(&path, |_| Err(io::Error::other("disconnected")));
+        assert!(result.is_err());
+        assert_eq!(fs::read(&path).unwrap(), before);
+        assert_eq!(fs::read_dir(dir).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_retry_transient_errors() {
+        let backoff = Duration::from_millis(1);
+        let mut calls = 0;
+        let result = with_retry(3, backoff, || {
+            calls += 1;
+            if calls <= 2 {
+                Err(io::Error::from(io::ErrorKind::TimedOut))
+            } else {
+                open_wav(Path::new("test/test.wav"))
+            }
+        });
+        assert!(result.is_ok());
+        assert_eq!(calls, 3);
+
+        let mut calls = 0;
+        let result: io::Result<()> = with_retry(3, backoff, || {
+            calls += 1;
+            Err(io::Error::from(io::ErrorKind::InvalidData))
+        });
+        assert!(result.is_err());
+        assert_eq!(calls, 1);
+
+        let mut calls = 0;
+        let result: io::Result<()> = with_retry(2, backoff, || {
+            calls += 1;
+            Err(io::Error::from(io::ErrorKind::TimedOut))
+        });
+        assert!(result.is_err());
+        assert_eq!(calls, 3);
+    }
+
+    #[test]
+    fn test_read_only_source_is_left_alone() {
+        let path = Path::new("test/readonly/source.wav");
+        write_stereo(path);
+        let original = fs::read(path).unwrap();
+        let mut permissions = fs::metadata(path).unwrap().permissions();
+        permissions.set_readonly(true);
+        fs::set_permissions(path, permissions.clone()).unwrap();
+
+        let e = wav_file_to_mono(path).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::PermissionDenied);
+        let e = wav_file_to_mono_verified(path).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::PermissionDenied);
+        assert_eq!(fs::read(path).unwrap(), original);
+
+        #[allow(clippy::permissions_set_readonly_false)]
+        permissions.set_readonly(false);
+        fs::set_permissions(path, permissions).unwrap();
+    }
+
+    #[test]
+    fn test_multichannel_leave_in_place() {
+        let dir = Path::new("test/multichannel");
+        let _ = fs::remove_dir_all(dir);
+        let surround = Header::new(WAV_FORMAT_PCM, 6, 48000, 16);
+        write_wav_file(
+            &dir.join("surround.wav"),
+            surround,
+            &BitDepth::Sixteen((0..60).collect()),
+        );
+        write_stereo(&dir.join("stereo.wav"));
+        let original = fs::read(dir.join("surround.wav")).unwrap();
+        assert_eq!(read_header(&dir.join("surround.wav")).unwrap(), surround);
+
+        let options = BatchOptions {
+            multichannel: MultichannelPolicy::LeaveInPlace,
+            ..BatchOptions::default()
+        };
+        wav_files_to_mono_with("test/multichannel", &options).unwrap();
+        assert_eq!(fs::read(dir.join("surround.wav")).unwrap(), original);
+        assert_eq!(fs::read_dir(dir).unwrap().count(), 2);
+        assert_eq!(
+            read_header(&dir.join("stereo.wav")).unwrap().channel_count,
+            1
+        );
+    }
+
+    #[test]
+    fn test_reprocessing_is_a_no_op() {
+        let dir = Path::new("test/idempotent");
+        let _ = fs::remove_dir_all(dir);
+        let mono = dir.join("mono.wav");
+        write_wav_file(
+            &mono,
+            Header::new(WAV_FORMAT_PCM, 1, 44100, 16),
+            &BitDepth::Sixteen(vec![1, 2, 3]),
+        );
+        let modified = fs::metadata(&mono).unwrap().modified().unwrap();
+        std::thread::sleep(Duration::from_millis(20));
+        wav_file_to_mono(&mono).unwrap();
+        assert_eq!(fs::metadata(&mono).unwrap().modified().unwrap(), modified);
+
+        let stereo = dir.join("stereo.wav");
+        write_stereo(&stereo);
+        let output = mono_output_path(&stereo).unwrap();
+        assert!(!is_already_converted(&stereo, &output).unwrap());
+        let mut wav = Wav::open(&stereo);
+        wav.to_mono().write(&output).unwrap();
+        assert!(is_already_converted(&stereo, &output).unwrap());
+        // a different file under the same name is not a match
+        assert!(!is_already_converted(Path::new("test/test.wav"), &output).unwrap());
+        // nor is the input once it has been edited after the conversion
+        let later = fs::metadata(&output).unwrap().modified().unwrap() + Duration::from_secs(1);
+        let source = OpenOptions::new().write(true).open(&stereo).unwrap();
+        source.set_modified(later).unwrap();
+        assert!(!is_already_converted(&stereo, &output).unwrap());
+    }
+
+    #[test]
+    fn test_output_colliding_with_input() {
+        let input = Path::new("test/collision/stereo.wav");
+        let _ = fs::remove_dir_all("test/collision");
+        write_stereo(input);
+        let before = fs::read(input).unwrap();
+
+        let output = output_path(input, ".").unwrap();
+        let e = check_distinct(input, &output).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(
+            wav_error(&e),
+            Some(&WavError::SameInputOutput {
+                output: output.clone()
+            })
+        );
+        assert_eq!(fs::read(input).unwrap(), before);
+        check_distinct(input, &mono_output_path(input).unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_batch_events() {
+        let dir = Path::new("test/events");
+        let _ = fs::remove_dir_all(dir);
+        write_stereo(&dir.join("a.wav"));
+        write_stereo(&dir.join("b.wav"));
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let options = BatchOptions {
+            events: Some(sender),
+            ..Default::default()
+        };
+        wav_files_to_mono_with("test/events", &options).unwrap();
+        drop(options);
+
+        let (a, b) = (dir.join("a.wav"), dir.join("b.wav"));
+        let events: Vec<_> = receiver.iter().collect();
+        assert_eq!(events.len(), 4);
+        assert_eq!(events[0], BatchEvent::Started { path: a.clone() });
+        let BatchEvent::Finished {
+            path,
+            elapsed,
+            crc32: checksum,
+            ..
+        } = &events[1]
+        else {
+            panic!("expected Finished, got {:?}", events[1]);
+        };
+        assert_eq!(*path, a);
+        assert!(!elapsed.is_zero());
+        assert_eq!(*checksum, crc32(&fs::read(&a).unwrap()));
+        assert_eq!(events[2], BatchEvent::Started { path: b.clone() });
+        assert!(matches!(&events[3], BatchEvent::Finished { path, .. } if *path == b));
+    }
+
+    #[test]
+    fn test_rf64_is_named() {
+        let path = Path::new("test/rf64/long.wav");
+        let _ = fs::remove_dir_all("test/rf64");
+        write_stereo(path);
+        let mut bytes = fs::read(path).unwrap();
+        bytes[0..4].copy_from_slice(b"RF64");
+        chunk::write_u32(&mut bytes, 4, u32::MAX);
+        fs::write(path, bytes).unwrap();
+
+        let e = open_wav(path).unwrap_err();
+        assert_eq!(wav_error(&e), Some(&WavError::Rf64Unsupported));
+    }
+
+    #[test]
+    fn test_split_selected_channels() {
+        let input = Path::new("test/split/quad.wav");
+        let _ = fs::remove_dir_all("test/split");
+        let data = test_utils::pcm16(&[[100, 200, 300, 400], [101, 201, 301, 401]]);
+        write_wav_file(input, Header::new(WAV_FORMAT_PCM, 4, 48000, 16), &data);
+
+        let wav = Wav::open(input);
+        for (&channel, mono) in [0, 3].iter().zip(wav.split_channels(&[0, 3]).unwrap()) {
+            mono.write(&channel_output_path(input, channel).unwrap())
+                .unwrap();
+        }
+        let mut written: Vec<_> = fs::read_dir("test/split/split")
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect();
+        written.sort();
+        assert_eq!(written, ["quad_ch0.wav", "quad_ch3.wav"]);
+        let (header, data) = open_wav(Path::new("test/split/split/quad_ch3.wav")).unwrap();
+        assert_eq!(header.channel_count, 1);
+        assert_eq!(data, BitDepth::Sixteen(vec![400, 401]));
+
+        let e = split_channels(wav.header, &wav.data, &[0, 4]).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_non_utf8_names_survive() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        // "録音.wav" in Shift_JIS
+        let name = OsStr::from_bytes(b"\x98\x5e\x89\xb9.wav");
+        let input = Path::new("test/sjis").join(name);
+        let mono = mono_output_path(&input).unwrap();
+        assert_eq!(mono.file_name(), Some(name));
+        let split = channel_output_path(&input, 1).unwrap();
+        assert_eq!(
+            split.file_name().unwrap().as_bytes(),
+            b"\x98\x5e\x89\xb9_ch1.wav"
+        );
+    }
+
+    #[test]
+    fn test_silent_files_left_in_place() {
+        let dir = Path::new("test/silence");
+        let _ = fs::remove_dir_all(dir);
+        let header = Header::new(WAV_FORMAT_PCM, 2, 44100, 16);
+        let quiet = dir.join("quiet.wav");
+        write_wav_file(&quiet, header, &test_utils::pcm16(&[[0, 1], [-1, 0]]));
+        let take = test_utils::pcm16(&[[1000, -1000], [2000, -2000]]);
+        write_wav_file(&dir.join("take.wav"), header, &take);
+        let before = fs::read(&quiet).unwrap();
+
+        let options = BatchOptions {
+            silence: SilencePolicy::LeaveInPlace,
+            ..Default::default()
+        };
+        wav_files_to_mono_with("test/silence", &options).unwrap();
+        assert_eq!(fs::read(&quiet).unwrap(), before);
+        assert_eq!(read_header(&dir.join("take.wav")).unwrap().channel_count, 1);
+    }
+
+    #[test]
+    fn test_stop_or_keep_going_after_a_failure() {
+        let dir = Path::new("test/keep_going");
+        for keep_going in [false, true] {
+            let _ = fs::remove_dir_all(dir);
+            write_stereo(&dir.join("a.wav"));
+            fs::write(dir.join("b.wav"), b"not a wav file").unwrap();
+            write_stereo(&dir.join("c.wav"));
+            let options = BatchOptions {
+                keep_going,
+                ..Default::default()
+            };
+            assert!(wav_files_to_mono_with("test/keep_going", &options).is_err());
+            assert_eq!(read_header(&dir.join("a.wav")).unwrap().channel_count, 1);
+            let c = read_header(&dir.join("c.wav")).unwrap().channel_count;
+            assert_eq!(c, if keep_going { 1 } else { 2 });
+        }
+    }
+
+    #[test]
+    fn test_keep_going_past_a_state_file_failure() {
+        let dir = Path::new("test/keep_going_state");
+        let _ = fs::remove_dir_all(dir);
+        write_stereo(&dir.join("a.wav"));
+        write_stereo(&dir.join("b.wav"));
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let options = BatchOptions {
+            keep_going: true,
+            // its folder doesn't exist, so every append fails
+            state_file: Some(dir.join("missing/state.txt")),
+            events: Some(sender),
+            ..Default::default()
+        };
+        let e = wav_files_to_mono_with("test/keep_going_state", &options).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::NotFound);
+        drop(options);
+
+        assert_eq!(read_header(&dir.join("b.wav")).unwrap().channel_count, 1);
+        let failed = receiver
+            .iter()
+            .filter(|event| matches!(event, BatchEvent::Failed { .. }))
+            .count();
+        assert_eq!(failed, 2);
+    }
+
+    #[test]
+    fn test_keep_going_past_a_policy_check_failure() {
+        let dir = Path::new("test/keep_going_policy");
+        let _ = fs::remove_dir_all(dir);
+        let header = Header::new(WAV_FORMAT_PCM, 2, 44100, 16);
+        let data = test_utils::pcm16(&[[1000, -1000], [2000, -2000]]);
+        write_wav_file(&dir.join("a.wav"), header, &data);
+        fs::write(dir.join("b.wav"), b"not a wav file").unwrap();
+        write_wav_file(&dir.join("c.wav"), header, &data);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let options = BatchOptions {
+            keep_going: true,
+            silence: SilencePolicy::LeaveInPlace,
+            events: Some(sender),
+            ..Default::default()
+        };
+        assert!(wav_files_to_mono_with("test/keep_going_policy", &options).is_err());
+        drop(options);
+
+        assert_eq!(read_header(&dir.join("c.wav")).unwrap().channel_count, 1);
+        let failed: Vec<_> = receiver
+            .iter()
+            .filter_map(|event| match event {
+                BatchEvent::Failed { path, .. } => Some(path),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(failed, [dir.join("b.wav")]);
+    }
+
+    #[test]
+    fn test_downmix_without_lfe() {
+        let header = Header::new(WAV_FORMAT_PCM, 6, 48000, 16);
+        // FL, FR, FC, LFE, BL, BR
+        let data = test_utils::pcm16(&[[100, 100, 100, 20000, 100, 100]; 4]);
+        let (_, mixed) = to_mono_mixed_lfe(header, data.clone(), DownmixGain::Sum, 0.0).unwrap();
+        assert_eq!(mixed, BitDepth::Sixteen(vec![500; 4]));
+        let (_, half) = to_mono_mixed_lfe(header, data, DownmixGain::Sum, 0.5).unwrap();
+        assert_eq!(half, BitDepth::Sixteen(vec![10500; 4]));
+    }
+
+    #[test]
+    fn test_zero_channels_rejected() {
+        let path = Path::new("test/zero_channels/broken.wav");
+        let _ = fs::remove_dir_all("test/zero_channels");
+        write_stereo(path);
+        let mut bytes = fs::read(path).unwrap();
+        // channel count field of the fmt chunk
+        bytes[22..24].copy_from_slice(&0u16.to_le_bytes());
+        fs::write(path, &bytes).unwrap();
+
+        let e = wav_file_to_mono(path).unwrap_err();
+        assert_eq!(
+            wav_error(&e),
+            Some(&WavError::InvalidChannelCount { channels: 0 })
+        );
+        assert_eq!(fs::read(path).unwrap(), bytes);
+        let e = open_wav_repaired(path).err().unwrap();
+        assert_eq!(
+            wav_error(&e),
+            Some(&WavError::InvalidChannelCount { channels: 0 })
+        );
+
+        // the public conversions refuse it too, rather than panicking
+        let header = Header::new(WAV_FORMAT_PCM, 0, 44100, 16);
+        let data = BitDepth::Sixteen(vec![1000, -1000]);
+        assert_eq!(source_channel(&header, &data), 0);
+        assert!(to_mono(header, data.clone()).is_none());
+        assert!(to_mono_mixed(header, data.clone(), DownmixGain::Sum).is_none());
+        assert!(to_mono_mixed_lfe(header, data.clone(), DownmixGain::Sum, 0.0).is_none());
+        assert!(to_mono_weighted(header, data.clone(), &[]).is_err());
+        assert!(split_channels(header, &data, &[]).is_err());
+    }
+
+    #[test]
+    fn test_split_then_recombine_is_lossless() {
+        let header = Header::new(WAV_FORMAT_PCM, 2, 44100, 16);
+        let data = test_utils::pcm16(&[[100, -200], [300, -400], [500, -600]]);
+        let monos = split_channels(header, &data, &[0, 1]).unwrap();
+        assert_eq!(recombine(&monos).unwrap(), (header, data));
+
+        let other = (
+            Header::new(WAV_FORMAT_PCM, 1, 48000, 16),
+            monos[1].1.clone(),
+        );
+        let e = recombine(&[monos[0].clone(), other]).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_truncated_frame() {
+        let path = Path::new("test/truncated_frame/cut.wav");
+        let _ = fs::remove_dir_all("test/truncated_frame");
+        // five 16-bit samples in a stereo file: the last frame has no right sample
+        let header = Header::new(WAV_FORMAT_PCM, 2, 44100, 16);
+        write_wav_file(
+            path,
+            header,
+            &BitDepth::Sixteen(vec![100, 200, 300, 400, 500]),
+        );
+
+        let e = open_wav(path).unwrap_err();
+        assert_eq!(
+            wav_error(&e),
+            Some(&WavError::TruncatedFrame {
+                channels: 2,
+                extra_samples: 1
+            })
+        );
+        let (_, data, _) = open_wav_repaired(path).unwrap();
+        assert_eq!(data, BitDepth::Sixteen(vec![100, 200, 300, 400, 500]));
+    }
+
+    #[test]
+    fn test_max_files_blocks_the_run() {
+        let dir = Path::new("test/max_files");
+        let _ = fs::remove_dir_all(dir);
+        for name in ["a.wav", "b.wav", "c.wav"] {
+            write_stereo(&dir.join(name));
+        }
+        let mut options = BatchOptions {
+            max_files: Some(2),
+            ..Default::default()
+        };
+        let e = wav_files_to_mono_with("test/max_files", &options).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(
+            wav_error(&e),
+            Some(&WavError::TooManyFiles { files: 3, max: 2 })
+        );
+        assert_eq!(read_header(&dir.join("a.wav")).unwrap().channel_count, 2);
+
+        options.max_files = Some(3);
+        wav_files_to_mono_with("test/max_files", &options).unwrap();
+        assert_eq!(read_header(&dir.join("a.wav")).unwrap().channel_count, 1);
+    }
+
+    #[test]
+    fn test_output_list_names_converted_files() {
+        let dir = Path::new("test/output_list");
+        let _ = fs::remove_dir_all(dir);
+        for name in ["a.wav", "b.wav", "c.wav"] {
+            write_stereo(&dir.join(name));
+        }
+        let mono = Header::new(WAV_FORMAT_PCM, 1, 44100, 16);
+        write_wav_file(&dir.join("d.wav"), mono, &BitDepth::Sixteen(vec![1000; 4]));
+        // stops the batch, after which the list must still be written
+        fs::write(dir.join("e.wav"), b"broken").unwrap();
+        let list = dir.join("outputs.txt");
+        let options = BatchOptions {
+            state_file: Some(dir.join("state.txt")),
+            output_list: Some(list.clone()),
+            ..Default::default()
+        };
+        let done = fs::canonicalize(dir.join("b.wav")).unwrap();
+        fs::write(dir.join("state.txt"), format!("{}\n", done.display())).unwrap();
+        assert!(wav_files_to_mono_with("test/output_list", &options).is_err());
+        assert_eq!(
+            fs::read_to_string(&list).unwrap(),
+            "test/output_list/a.wav\ntest/output_list/c.wav\n"
+        );
+    }
+
+    #[test]
+    fn test_channel_limit() {
+        let dir = Path::new("test/channel_limit");
+        let _ = fs::remove_dir_all(dir);
+        let path = dir.join("wide.wav");
+        let header = Header::new(WAV_FORMAT_PCM, 24, 48000, 16);
+        write_wav_file(&path, header, &BitDepth::Sixteen(vec![1000; 24 * 4]));
+        let before = fs::read(&path).unwrap();
+
+        let mut options = BatchOptions {
+            max_channels: Some(16),
+            ..Default::default()
+        };
+        let e = wav_files_to_mono_with("test/channel_limit", &options).unwrap_err();
+        assert_eq!(
+            wav_error(&e),
+            Some(&WavError::TooManyChannels {
+                channels: 24,
+                max: 16
+            })
+        );
+        assert_eq!(fs::read(&path).unwrap(), before);
+
+        options.max_channels = Some(24);
+        wav_files_to_mono_with("test/channel_limit", &options).unwrap();
+        assert_eq!(read_header(&path).unwrap().channel_count, 1);
+    }
+
+    #[test]
+    fn test_sort_order() {
+        let dir = Path::new("test/sort_order");
+        let _ = fs::remove_dir_all(dir);
+        let header = Header::new(WAV_FORMAT_PCM, 2, 44100, 16);
+        // c.wav is the shortest and a.wav the longest
+        for (name, frames) in [("a.wav", 3), ("b.wav", 2), ("c.wav", 1)] {
+            write_wav_file(
+                &dir.join(name),
+                header,
+                &BitDepth::Sixteen(vec![1000; frames * 2]),
+            );
+        }
+        let manifest = dir.join("list.txt");
+        fs::write(&manifest, "b.wav\nc.wav\na.wav\n").unwrap();
+
+        let started = |order| {
+            let (sender, receiver) = std::sync::mpsc::channel();
+            let options = BatchOptions {
+                order,
+                events: Some(sender),
+                ..Default::default()
+            };
+            wav_files_to_mono_listed(&manifest, &options).unwrap();
+            drop(options);
+            receiver
+                .iter()
+                .filter_map(|event| match event {
+                    BatchEvent::Started { path } => Some(path.file_name().unwrap().to_owned()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(started(SortOrder::AsListed), ["b.wav", "c.wav", "a.wav"]);
+        assert_eq!(
+            started(SortOrder::Alphabetical),
+            ["a.wav", "b.wav", "c.wav"]
+        );
+        assert_eq!(started(SortOrder::BySize), ["c.wav", "b.wav", "a.wav"]);
+    }
+
+    #[test]
+    fn test_state_tells_same_names_apart() {
+        let dir = Path::new("test/manifest_state");
+        let _ = fs::remove_dir_all(dir);
+        write_stereo(&dir.join("x/take.wav"));
+        fs::write(dir.join("x/broken.wav"), b"broken").unwrap();
+        write_stereo(&dir.join("y/take.wav"));
+        let manifest = dir.join("list.txt");
+        fs::write(&manifest, "x/take.wav\nx/broken.wav\ny/take.wav\n").unwrap();
+        let options = BatchOptions {
+            state_file: Some(dir.join("state.txt")),
+            ..Default::default()
+        };
+
+        // stops at broken.wav, with only x/take.wav done
+        assert!(wav_files_to_mono_listed(&manifest, &options).is_err());
+        fs::remove_file(dir.join("x/broken.wav")).unwrap();
+        fs::write(&manifest, "x/take.wav\ny/take.wav\n").unwrap();
+        wav_files_to_mono_listed(&manifest, &options).unwrap();
+        assert_eq!(
+            read_header(&dir.join("y/take.wav")).unwrap().channel_count,
+            1
+        );
+        assert_eq!(
+            fs::read_to_string(dir.join("state.txt"))
+                .unwrap()
+                .lines()
+                .count(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_manifest_picks_files() {
+        let dir = Path::new("test/manifest");
+        let _ = fs::remove_dir_all(dir);
+        for name in ["a.wav", "b.wav", "c.wav", "more/d.wav"] {
+            write_stereo(&dir.join(name));
+        }
+        let manifest = dir.join("list.txt");
+        fs::write(&manifest, "# takes to keep\na.wav\n\nmore\n").unwrap();
+
+        wav_files_to_mono_listed(&manifest, &BatchOptions::default()).unwrap();
+        let channels = |name| read_header(&dir.join(name)).unwrap().channel_count;
+        assert_eq!(
+            ["a.wav", "b.wav", "c.wav", "more/d.wav"].map(channels),
+            [1, 2, 2, 1]
+        );
+    }
+
+    #[test]
+    fn test_write_existing_needs_the_folder() {
+        let input = Path::new("test/no_create/stereo.wav");
+        let _ = fs::remove_dir_all("test/no_create");
+        write_stereo(input);
+        let before = fs::read(input).unwrap();
+
+        let output = mono_output_path(input).unwrap();
+        let mut wav = Wav::open(input);
+        let e = wav.to_mono().write_existing(&output).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::NotFound);
+        assert_eq!(
+            wav_error(&e),
+            Some(&WavError::OutputDirMissing {
+                dir: output.parent().unwrap().to_owned()
+            })
+        );
+        assert!(!output.parent().unwrap().exists());
+        assert_eq!(fs::read(input).unwrap(), before);
+
+        fs::create_dir(output.parent().unwrap()).unwrap();
+        wav.write_existing(&output).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlinks() {
+        let dir = Path::new("test/symlinks");
+        for symlinks in [SymlinkPolicy::LeaveInPlace, SymlinkPolicy::Follow] {
+            let _ = fs::remove_dir_all(dir);
+            let target = dir.join("takes/take.wav");
+            write_stereo(&target);
+            fs::create_dir(dir.join("links")).unwrap();
+            let link = dir.join("links/take.wav");
+            std::os::unix::fs::symlink("../takes/take.wav", &link).unwrap();
+
+            let options = BatchOptions {
+                symlinks,
+                ..Default::default()
+            };
+            wav_files_to_mono_with("test/symlinks/links", &options).unwrap();
+            assert!(fs::symlink_metadata(&link)
+                .unwrap()
+                .file_type()
+                .is_symlink());
+            let channels = read_header(&target).unwrap().channel_count;
+            assert_eq!(
+                channels,
+                if symlinks == SymlinkPolicy::Follow {
+                    1
+                } else {
+                    2
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn test_warnings_do_not_fail_the_file() {
+        let dir = Path::new("test/warnings");
+        let _ = fs::remove_dir_all(dir);
+        let header = Header::new(WAV_FORMAT_PCM, 2, 44100, 16);
+        // silent left channel, right channel clipped for three samples
+        let data = test_utils::pcm16(&[[0, i16::MAX], [0, i16::MAX], [0, i16::MAX], [0, 100]]);
+        write_wav_file(&dir.join("hot.wav"), header, &data);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let options = BatchOptions {
+            events: Some(sender),
+            ..Default::default()
+        };
+        wav_files_to_mono_with("test/warnings", &options).unwrap();
+        drop(options);
+
+        let finished = receiver.iter().find_map(|event| match event {
+            BatchEvent::Finished { warnings, .. } => Some(warnings),
+            _ => None,
+        });
+        assert_eq!(
+            finished.unwrap(),
+            [
+                ConversionWarning::SilentChannelSkipped { used: 1 },
+                ConversionWarning::Clipped { samples: 3 }
+            ]
+        );
+    }
+
+    #[test]
+    fn test_modified_after() {
+        let dir = Path::new("test/modified_after");
+        let _ = fs::remove_dir_all(dir);
+        let now = SystemTime::now();
+        for (name, age) in [("old.wav", 3600), ("new.wav", 0)] {
+            let path = dir.join(name);
+            write_stereo(&path);
+            let file = File::options().write(true).open(&path).unwrap();
+            file.set_modified(now - Duration::from_secs(age)).unwrap();
+        }
+        let options = BatchOptions {
+            modified_after: Some(now - Duration::from_secs(60)),
+            ..Default::default()
+        };
+        wav_files_to_mono_with("test/modified_after", &options).unwrap();
+        assert_eq!(read_header(&dir.join("old.wav")).unwrap().channel_count, 2);
+        assert_eq!(read_header(&dir.join("new.wav")).unwrap().channel_count, 1);
+    }
+
+    #[test]
+    fn test_missing_data_chunk() {
+        let path = Path::new("test/no_data/header_only.wav");
+        let _ = fs::remove_dir_all("test/no_data");
+        let header = Header::new(WAV_FORMAT_PCM, 2, 44100, 16);
+        let bytes = test_utils::build_wav(header, &BitDepth::Sixteen(vec![100, 200]));
+        let fmt = chunk::chunks(&bytes).unwrap()[0];
+        assert_eq!(&fmt.id, b"fmt ");
+        let mut bytes = bytes[..fmt.data_start() + fmt.size as usize].to_vec();
+        chunk::fix_riff_size(&mut bytes);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, bytes).unwrap();
+
+        let e = open_wav(path).unwrap_err();
+        assert_eq!(wav_error(&e), Some(&WavError::NoDataChunk));
+        let e = wav_file_to_mono(path).unwrap_err();
+        assert_eq!(wav_error(&e), Some(&WavError::NoDataChunk));
+    }
+
+    #[test]
+    fn test_weighted_downmix() {
+        let header = Header::new(WAV_FORMAT_PCM, 2, 48000, 16);
+        let frames = [[1000, -1000], [20000, 10000], [i16::MAX, i16::MAX]];
+        let data = test_utils::pcm16(&frames);
+        let (mono, mixed) = to_mono_weighted(header, data.clone(), &[0.7, 0.3]).unwrap();
+        assert_eq!(mono.channel_count, 1);
+        let expected: Vec<i16> = frames
+            .iter()
+            .map(|[l, r]| (0.7 * *l as f32 + 0.3 * *r as f32).round() as i16)
+            .collect();
+        let BitDepth::Sixteen(mixed) = mixed else {
+            panic!("expected 16-bit output");
+        };
+        for (m, e) in mixed.iter().zip(&expected) {
+            assert!((m - e).abs() <= 1, "{} vs {}", m, e);
+        }
+
+        let e = to_mono_weighted(header, data, &[1.0]).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_concat_mono() {
+        let dir = Path::new("test/concat");
+        let _ = fs::remove_dir_all(dir);
+        let header = Header::new(WAV_FORMAT_PCM, 2, 1000, 16);
+        let first = dir.join("first.wav");
+        write_wav_file(
+            &first,
+            header,
+            &test_utils::pcm16(&[[100, 100], [200, 200]]),
+        );
+        let second = dir.join("second.wav");
+        write_wav_file(&second, header, &test_utils::pcm16(&[[300, 300]; 3]));
+
+        let joined = concat_mono(&[first.clone(), second], Duration::from_millis(2)).unwrap();
+        assert_eq!(joined.header.channel_count, 1);
+        assert_eq!(
+            joined.data,
+            BitDepth::Sixteen(vec![100, 200, 0, 0, 300, 300, 300])
+        );
+
+        let other = dir.join("other.wav");
+        write_wav_file(
+            &other,
+            Header::new(WAV_FORMAT_PCM, 2, 2000, 16),
+            &test_utils::pcm16(&[[100, 100]]),
+        );
+        let e = concat_mono(&[first, other], Duration::ZERO).err().unwrap();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_name_filter() {
+        let dir = Path::new("test/filter");
+        let _ = fs::remove_dir_all(dir);
+        for name in ["a_mix.wav", "b_mix.wav", "a_vox.wav", "mix.wav"] {
+            write_stereo(&dir.join(name));
+        }
+        let options = BatchOptions {
+            name_filter: Some("*_mix.wav".to_owned()),
+            ..BatchOptions::default()
+        };
+        wav_files_to_mono_with("test/filter", &options).unwrap();
+
+        for (name, channels) in [
+            ("a_mix.wav", 1),
+            ("b_mix.wav", 1),
+            ("a_vox.wav", 2),
+            ("mix.wav", 2),
+        ] {
+            let (header, _) = open_wav(&dir.join(name)).unwrap();
+            assert_eq!(header.channel_count, channels, "{}", name);
+        }
+        assert!(glob_match("take_??.wav", "take_01.wav"));
+        assert!(!glob_match("take_??.wav", "take_1.wav"));
+    }
+
+    #[test]
+    fn test_repair_zero_data_chunk_size() {
+        let path = Path::new("test/repair/zero_size.wav");
+        write_zero_size_stereo(path);
+
+        let (_, data) = open_wav(path).unwrap();
+        assert_eq!(data, BitDepth::Sixteen(vec![]));
+        let (_, data, repaired) = open_wav_repaired(path).unwrap();
+        assert!(repaired);
+        assert_eq!(
+            data,
+            BitDepth::Sixteen(vec![1000, -1000, 2000, 1500, -3000, 500])
+        );
+
+        let (_, _, repaired) = open_wav_repaired(Path::new("test/test.wav")).unwrap();
+        assert!(!repaired);
+    }
+
+    #[test]
+    fn test_batch_repairs_when_asked() {
+        let dir = Path::new("test/batch_repair");
+        let _ = fs::remove_dir_all(dir);
+        let path = dir.join("crashed.wav");
+        write_zero_size_stereo(&path);
+
+        // unrepaired, the declared size is taken at its word and the audio lost
+        wav_files_to_mono_with("test/batch_repair", &BatchOptions::default()).unwrap();
+        let (_, data) = open_wav(&path).unwrap();
+        assert_eq!(analysis::sample_count(&data), 0);
+
+        write_zero_size_stereo(&path);
+        let options = BatchOptions {
+            repair: true,
+            ..Default::default()
+        };
+        wav_files_to_mono_with("test/batch_repair", &options).unwrap();
+        let (header, data) = open_wav(&path).unwrap();
+        assert_eq!(header.channel_count, 1);
+        assert_eq!(analysis::sample_count(&data), 3);
+    }
+
+    // write_stereo, with the data chunk size zeroed as a crashed recorder leaves it.
+    fn write_zero_size_stereo(path: &Path) {
+        write_stereo(path);
+        let mut bytes = fs::read(path).unwrap();
+        let data = chunk::chunks(&bytes)
+            .unwrap()
+            .into_iter()
+            .find(|c| &c.id == b"data")
+            .unwrap();
+        chunk::write_u32(&mut bytes, data.offset + 4, 0);
+        fs::write(path, bytes).unwrap();
+    }
+
+    #[test]
+    fn test_write_comment() {
+        let path = Path::new("test/comment/test.wav");
+        let mut wav = Wav::open(Path::new("test/test.wav"));
+        wav.to_mono().with_comment(&conversion_comment());
+        wav.write(path).unwrap();
+
+        let bytes = fs::read(path).unwrap();
+        let contents = chunk::find(&bytes, b"LIST").unwrap().unwrap();
+        assert_eq!(&contents[0..4], b"INFO");
+        assert_eq!(&contents[4..8], b"ICMT");
+        let text = format!("{}\0", conversion_comment());
+        assert!(contents[12..].starts_with(text.as_bytes()));
+        assert!(text.contains(env!("CARGO_PKG_VERSION")));
+
+        let (header, _) = open_wav(path).unwrap();
+        assert_eq!(header.channel_count, 1);
+    }
+
+    fn noise(seed: u32, len: usize) -> Vec<i16> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+                ((state >> 16) as i16) / 4
+            })
+            .collect()
+    }
+
+    fn rms(data: &BitDepth) -> f64 {
+        let samples = samples_f32(data);
+        let sum: f64 = samples.iter().map(|&s| (s as f64).powi(2)).sum();
+        (sum / samples.len() as f64).sqrt()
+    }
+
+    #[test]
+    fn test_downmix_gain_levels() {
+        let (left, right) = (noise(1, 4096), noise(2, 4096));
+        let interleaved: Vec<i16> = left
+            .iter()
+            .zip(&right)
+            .flat_map(|(&l, &r)| [l, r])
+            .collect();
+        let header = Header::new(WAV_FORMAT_PCM, 2, 44100, 16);
+        let level = |gain| {
+            let data = BitDepth::Sixteen(interleaved.clone());
+            rms(&to_mono_mixed(header, data, gain).unwrap().1)
+        };
+        let (mean, sum, minus3) = (
+            level(DownmixGain::Mean),
+            level(DownmixGain::Sum),
+            level(DownmixGain::MinusThreeDb),
+        );
+        assert!((sum / mean - 2.0).abs() < 0.01);
+        assert!((minus3 / mean - 2.0 * 10f64.powf(-3.0 / 20.0)).abs() < 0.01);
+        // uncorrelated channels: the mean is about 3 dB below either channel
+        let channel = rms(&BitDepth::Sixteen(left));
+        assert!((20.0 * (mean / channel).log10() + 3.0).abs() < 0.5);
+
+        let loud = BitDepth::Sixteen(vec![30000, 30000, -30000, -30000]);
+        let (_, data) = to_mono_mixed(header, loud, DownmixGain::Sum).unwrap();
+        assert_eq!(data, BitDepth::Sixteen(vec![32767, -32768]));
+    }
+
+    // Outputs carry no timestamps or random padding, so archives can hash them.
+    #[test]
+    fn test_output_is_byte_stable() {
+        let input = Path::new("test/stable/stereo.wav");
+        let _ = fs::remove_dir_all("test/stable");
+        write_stereo(input);
+        let convert = |name: &str| {
+            let output = input.with_file_name(name);
+            let mut wav = Wav::open(input);
+            wav.to_mono().with_comment(&conversion_comment());
+            wav.write(&output).unwrap();
+            fs::read(output).unwrap()
+        };
+        assert_eq!(convert("first.wav"), convert("second.wav"));
+    }
+
+    #[test]
+    fn test_keep_sample_loops() {
+        let dir = Path::new("test/loops");
+        let mut smpl = Vec::new();
+        // manufacturer, product, period, unity note, pitch, smpte format,
+        // smpte offset, loop count, sampler data
+        for v in [0u32, 0, 22675, 60, 0, 0, 0, 1, 0] {
+            smpl.extend_from_slice(&v.to_le_bytes());
+        }
+        // loop id, type, start, end, fraction, play count
+        for v in [0u32, 0, 1, 2, 0, 0] {
+            smpl.extend_from_slice(&v.to_le_bytes());
+        }
+        let header = Header::new(WAV_FORMAT_PCM, 2, 44100, 16);
+        let data = BitDepth::Sixteen(vec![100, 100, 200, 200, 300, 300]);
+        let source = dir.join("looped.wav");
+        fs::create_dir_all(dir).unwrap();
+        write_wav_with_chunks(&source, header, &data, &[chunk::encode(b"smpl", &smpl)]).unwrap();
+
+        let output = dir.join("mono/looped.wav");
+        let mut wav = Wav::open(&source);
+        wav.to_mono().keep_sample_chunk(&source).unwrap();
+        wav.write(&output).unwrap();
+
+        let bytes = fs::read(&output).unwrap();
+        assert_eq!(chunk::find(&bytes, b"smpl").unwrap().unwrap(), &smpl[..]);
+        let (header, data) = open_wav(&output).unwrap();
+        assert_eq!(header.channel_count, 1);
+        assert_eq!(data, BitDepth::Sixteen(vec![100, 200, 300]));
+
+        let mut plain = Wav::open(Path::new("test/test.wav"));
+        plain.keep_sample_chunk(Path::new("test/test.wav")).unwrap();
+        assert!(plain.chunks.is_empty());
+    }
+
+    #[test]
+    fn test_header_override_to_float() {
+        let header = Header::new(WAV_FORMAT_PCM, 1, 44100, 16);
+        let mut wav = Wav::new(header, BitDepth::Sixteen(vec![16384, -32768, 0]));
+        let to_float = HeaderOverride {
+            audio_format: Some(WAV_FORMAT_IEEE_FLOAT),
+            bits_per_sample: Some(32),
+            sampling_rate: Some(48000),
+        };
+        wav.with_header_override(&to_float).unwrap();
+        let path = Path::new("test/override/float.wav");
+        wav.write(path).unwrap();
+
+        let (header, data) = open_wav(path).unwrap();
+        assert_eq!(header.audio_format, WAV_FORMAT_IEEE_FLOAT);
+        assert_eq!(header.bits_per_sample, 32);
+        assert_eq!(header.sampling_rate, 48000);
+        assert_eq!(header.channel_count, 1);
+        assert_eq!(data, BitDepth::ThirtyTwoFloat(vec![0.5, -1.0, 0.0]));
+
+        let float_16 = HeaderOverride {
+            audio_format: Some(WAV_FORMAT_IEEE_FLOAT),
+            bits_per_sample: Some(16),
+            ..HeaderOverride::default()
+        };
+        let e = apply_header_override(header, &data, &float_16).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+
+        let too_slow = HeaderOverride {
+            sampling_rate: Some(500),
+            ..HeaderOverride::default()
+        };
+        let e = apply_header_override(header, &data, &too_slow).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_compressed_wav_error() {
+        let path = Path::new("test/compressed/alaw.wav");
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let mut alaw = Header::new(0x0006, 2, 8000, 8);
+        alaw.bytes_per_sample = 2;
+        let fmt: [u8; 16] = alaw.into();
+        let mut bytes = b"RIFF\0\0\0\0WAVE".to_vec();
+        bytes.extend(chunk::encode(b"fmt ", &fmt));
+        bytes.extend(chunk::encode(b"data", &[0xd5; 16]));
+        chunk::fix_riff_size(&mut bytes);
+        fs::write(path, bytes).unwrap();
+
+        let e = open_wav(path).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(
+            wav_error(&e),
+            Some(&WavError::CompressedWavUnsupported {
+                format_tag: 6,
+                codec: "A-law"
+            })
+        );
+        assert!(e.to_string().contains("A-law"));
+    }
+
+    #[test]
+    fn test_decode_mulaw() {
+        let path = Path::new("test/compressed/mulaw.wav");
+        write_mulaw_stereo(path);
+
+        assert!(open_wav(path).is_err());
+        let (header, data) = open_wav_decoded(path).unwrap();
+        assert_eq!(header, Header::new(WAV_FORMAT_PCM, 2, 8000, 16));
+        assert_eq!(
+            data,
+            BitDepth::Sixteen(vec![32124, 32124, 120, 120, -32124, -32124])
+        );
+        let (_, data) = to_mono(header, data).unwrap();
+        assert_eq!(data, BitDepth::Sixteen(vec![32124, 120, -32124]));
+
+        let (_, data) = open_wav_decoded(Path::new("test/test.wav")).unwrap();
+        assert!(data.is_sixteen());
+    }
+
+    #[test]
+    fn test_batch_decodes_mulaw_when_asked() {
+        let dir = Path::new("test/batch_mulaw");
+        let _ = fs::remove_dir_all(dir);
+        let path = dir.join("phone.wav");
+        write_mulaw_stereo(&path);
+
+        assert!(wav_files_to_mono_with("test/batch_mulaw", &BatchOptions::default()).is_err());
+        let options = BatchOptions {
+            decode_g711: true,
+            ..Default::default()
+        };
+        wav_files_to_mono_with("test/batch_mulaw", &options).unwrap();
+        let (header, data) = open_wav(&path).unwrap();
+        assert_eq!(header, Header::new(WAV_FORMAT_PCM, 1, 8000, 16));
+        assert_eq!(data, BitDepth::Sixteen(vec![32124, 120, -32124]));
+    }
+
+    // A 2-channel 8 kHz µ-law file with the same codes on both channels.
+    fn write_mulaw_stereo(path: &Path) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let mut mulaw = Header::new(g711::WAV_FORMAT_MULAW, 2, 8000, 8);
+        mulaw.bytes_per_sample = 2;
+        let fmt: [u8; 16] = mulaw.into();
+        let mut bytes = b"RIFF\0\0\0\0WAVE".to_vec();
+        bytes.extend(chunk::encode(b"fmt ", &fmt));
+        // dual-mono: both channels carry the same codes
+        bytes.extend(chunk::encode(
+            b"data",
+            &[0x80, 0x80, 0xf0, 0xf0, 0x00, 0x00],
+        ));
+        chunk::fix_riff_size(&mut bytes);
+        fs::write(path, bytes).unwrap();
+    }
+
+    #[test]
+    fn test_zero_sample_rate() {
+        let path = Path::new("test/invalid/zero_rate.wav");
+        write_wav_file(
+            path,
+            Header::new(WAV_FORMAT_PCM, 2, 0, 16),
+            &BitDepth::Sixteen(vec![1, 2, 3, 4]),
+        );
+        let e = open_wav(path).unwrap_err();
+        assert_eq!(
+            wav_error(&e),
+            Some(&WavError::InvalidSampleRate { rate: 0 })
+        );
+        assert!(wav_file_to_mono(path).is_err());
+    }
+
+    #[test]
+    fn test_supported_formats() {
+        let dir = Path::new("test/formats");
+        for &(format, bits) in supported_formats() {
+            let header = Header::new(format, 2, 44100, bits);
+            let data = match (format, bits) {
+                (WAV_FORMAT_PCM, 8) => BitDepth::Eight(vec![1, 2, 3, 4]),
+                (WAV_FORMAT_PCM, 16) => BitDepth::Sixteen(vec![1, 2, 3, 4]),
+                (WAV_FORMAT_PCM, 24) => BitDepth::TwentyFour(vec![1 << 8, 2 << 8, 3 << 8, 4 << 8]),
+                (WAV_FORMAT_IEEE_FLOAT, 32) => BitDepth::ThirtyTwoFloat(vec![0.1, 0.2, 0.3, 0.4]),
+                _ => panic!("no fixture for {:?}", (format, bits)),
+            };
+            let path = dir.join(format!("{}_{}.wav", format, bits));
+            write_wav_file(&path, header, &data);
+
+            let (header, data) = open_wav(&path).unwrap();
+            assert!(is_supported(&header));
+            let (header, _) = to_mono(header, data).unwrap();
+            assert_eq!(
+                (header.audio_format, header.bits_per_sample),
+                (format, bits)
+            );
+        }
+        let header = Header::new(WAV_FORMAT_PCM, 2, 44100, 32);
+        assert!(to_mono(header, BitDepth::ThirtyTwoFloat(vec![0.0; 4])).is_none());
+    }
+}
+
+// The GUI converts on a worker thread, so these types must stay shareable.
+#[cfg(test)]
+mod thread_safety {
+    use super::*;
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn test_public_types_are_send_sync() {
+        assert_send_sync::<Wav>();
+        assert_send_sync::<BatchOptions>();
+        assert_send_sync::<BatchEvent>();
+        assert_send_sync::<ConversionWarning>();
+        assert_send_sync::<Header>();
+        assert_send_sync::<BitDepth>();
+        assert_send_sync::<io::Error>();
+        assert_send_sync::<WavError>();
     }
 }
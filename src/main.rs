@@ -1,24 +1,54 @@
 #![windows_subsystem = "windows"]
+mod i18n;
+
+use std::collections::HashMap;
+use std::fs::File;
 use std::io;
+use std::panic;
+use std::panic::AssertUnwindSafe;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::sync::Mutex;
 use std::thread;
+use std::time::Duration;
+use std::time::Instant;
 
-use eframe::egui::viewport;
 use eframe::egui::ViewportBuilder;
-use wav2mono::Wav;
+use wav2mono::{
+    check_distinct, conversion_comment, is_already_converted, mono_output_path, open_wav,
+    open_wav_decoded, open_wav_repaired, output_path, peak_envelope, read_header, Wav,
+};
 
 use eframe::egui;
+use i18n::{Lang, Text};
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Default)]
 enum AppState {
+    #[default]
     Idle,
     Converting,
 }
 
-impl Default for AppState {
-    fn default() -> Self {
-        Self::Idle
+// What the worker did with the files of the current batch.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct DropReport {
+    processed: usize,
+    skipped: usize,
+    // outputs that already matched their input and were left alone
+    unchanged: usize,
+    failed: usize,
+    // drops with neither a path nor contents, e.g. from some browsers
+    unsupported: usize,
+}
+
+impl DropReport {
+    fn nothing_to_convert(&self) -> bool {
+        self.processed == 0
+            && self.unchanged == 0
+            && self.failed == 0
+            && self.skipped + self.unsupported > 0
     }
 }
 
@@ -26,28 +56,151 @@ impl Default for AppState {
 struct MyApp {
     dropped_files: Arc<Mutex<Vec<egui::DroppedFile>>>,
     app_state: Arc<Mutex<AppState>>,
+    report: Arc<Mutex<DropReport>>,
+    lang: Lang,
+    settings: ConvertSettings,
+    thumbnails: Thumbnails,
+    // the worker finishes its current file, then waits while this is set
+    paused: Arc<AtomicBool>,
+    // files whose conversion failed and why, waiting for Retry failed
+    failed: Arc<Mutex<Vec<(egui::DroppedFile, String)>>>,
+}
+
+// Per-channel peak envelopes of queued files, keyed by path.
+type Thumbnails = Arc<Mutex<HashMap<PathBuf, Vec<Vec<f32>>>>>;
+
+const THUMBNAIL_BUCKETS: usize = 48;
+
+// Output options chosen in the window, copied into each batch.
+#[derive(Debug, Clone, Default)]
+struct ConvertSettings {
+    add_comment: bool,
+    keep_loops: bool,
+    route_clipped: bool,
+    // outputs go one folder further down, named after the sample rate
+    route_by_rate: bool,
+    // output folders by channel count instead of mono/, as "4=quad, 6=surround"
+    channel_folders: String,
+    skip_multichannel: bool,
+    // expand µ-law and A-law files to 16-bit PCM before converting
+    decode_g711: bool,
+    // read to the end of files whose data chunk size is zero or too large
+    repair: bool,
+    // files converted at once; 0 means one per CPU
+    threads: usize,
+    // progress as a plain sentence and no waveform thumbnails, which
+    // screen readers can't describe
+    text_progress: bool,
 }
 
+impl ConvertSettings {
+    fn worker_count(&self) -> usize {
+        match self.threads {
+            0 => thread::available_parallelism().map_or(1, |n| n.get()),
+            n => n,
+        }
+    }
+
+    // The channel_folders entry for this count; malformed entries are ignored.
+    fn channel_folder(&self, channel_count: u16) -> Option<&str> {
+        self.channel_folders.split(',').find_map(|entry| {
+            let (count, folder) = entry.split_once('=')?;
+            let folder = folder.trim();
+            let matches = count.trim().parse() == Ok(channel_count) && !folder.is_empty();
+            matches.then_some(folder)
+        })
+    }
+}
+
+// Files with more than 0.1% clipped samples go to clipped/ for review.
+const CLIPPED_RATIO: f32 = 0.001;
+
 impl eframe::App for MyApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         egui::TopBottomPanel::bottom("bottom").show(ctx, |ui| {
             ui.horizontal(|ui| {
                 ui.spacing_mut().item_spacing.x = 0.0;
-                ui.label("developed by ");
+                ui.label(Text::DevelopedBy.get(self.lang));
                 ui.hyperlink_to("Atano", "https://twitter.com/AtanoOkakura");
                 egui::warn_if_debug_build(ui);
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                    for lang in Lang::ALL.into_iter().rev() {
+                        let picked = ui.selectable_value(&mut self.lang, lang, lang.name());
+                        if picked.changed() {
+                            if let Some(path) = i18n::settings_path() {
+                                // still switched for this session if saving fails
+                                let _ = i18n::save_lang(&path, lang);
+                            }
+                        }
+                    }
+                });
             });
         });
 
         egui::CentralPanel::default().show(ctx, |ui| {
-            ui.label("Drag-and-drop files onto the window!");
+            ui.label(Text::DropHint.get(self.lang));
+            ui.checkbox(
+                &mut self.settings.add_comment,
+                Text::AddComment.get(self.lang),
+            );
+            ui.checkbox(
+                &mut self.settings.keep_loops,
+                Text::KeepLoops.get(self.lang),
+            );
+            ui.checkbox(
+                &mut self.settings.route_clipped,
+                Text::RouteClipped.get(self.lang),
+            );
+            ui.checkbox(
+                &mut self.settings.route_by_rate,
+                Text::RouteByRate.get(self.lang),
+            );
+            ui.horizontal(|ui| {
+                ui.label(Text::ChannelFolders.get(self.lang));
+                ui.text_edit_singleline(&mut self.settings.channel_folders);
+            });
+            ui.checkbox(
+                &mut self.settings.skip_multichannel,
+                Text::SkipMultichannel.get(self.lang),
+            );
+            ui.checkbox(
+                &mut self.settings.decode_g711,
+                Text::DecodeG711.get(self.lang),
+            );
+            ui.checkbox(&mut self.settings.repair, Text::Repair.get(self.lang));
+            ui.checkbox(
+                &mut self.settings.text_progress,
+                Text::PlainProgress.get(self.lang),
+            );
+            let cpus = thread::available_parallelism().map_or(1, |n| n.get());
+            ui.add(
+                egui::Slider::new(&mut self.settings.threads, 0..=cpus)
+                    .text(Text::Threads.get(self.lang)),
+            );
+
+            // drops turned away at queue time never reach the list below
+            if let Some(text) = skipped_text(&self.report.lock().unwrap(), self.lang) {
+                ui.colored_label(ui.visuals().warn_fg_color, text);
+            }
 
-            let dropped_files = self.dropped_files.lock().unwrap();
+            let mut dropped_files = self.dropped_files.lock().unwrap();
             // Show dropped files (if any):
             if !dropped_files.is_empty() {
                 ui.group(|ui| {
-                    ui.label("Converting to mono:");
+                    ui.horizontal(|ui| {
+                        ui.label(Text::ConvertingToMono.get(self.lang));
+                        let paused = self.paused.load(Ordering::Relaxed);
+                        let toggle = if paused { Text::Resume } else { Text::Pause };
+                        if ui.button(toggle.get(self.lang)).clicked() {
+                            self.paused.store(!paused, Ordering::Relaxed);
+                        }
+                    });
 
+                    if self.settings.text_progress {
+                        let report = *self.report.lock().unwrap();
+                        ui.label(progress_text(&report, dropped_files.len(), self.lang));
+                    }
+                    let thumbnails = self.thumbnails.lock().unwrap();
                     for file in dropped_files.iter() {
                         let info = if let Some(path) = &file.path {
                             path.display().to_string()
@@ -57,9 +210,45 @@ impl eframe::App for MyApp {
                             "???".to_owned()
                         };
 
-                        ui.label(info);
+                        ui.horizontal(|ui| {
+                            if !self.settings.text_progress {
+                                let envelope = file.path.as_ref().and_then(|p| thumbnails.get(p));
+                                draw_thumbnail(ui, envelope.map(Vec::as_slice).unwrap_or_default());
+                            }
+                            ui.label(info);
+                        });
                     }
                 });
+            } else if matches!(*self.app_state.lock().unwrap(), AppState::Idle) {
+                if self.report.lock().unwrap().nothing_to_convert() {
+                    ui.colored_label(ui.visuals().warn_fg_color, Text::NoWavFiles.get(self.lang));
+                }
+                let unsupported = self.report.lock().unwrap().unsupported;
+                if unsupported > 0 {
+                    let text = Text::UnsupportedSource
+                        .get(self.lang)
+                        .replace("{count}", &unsupported.to_string());
+                    ui.colored_label(ui.visuals().warn_fg_color, text);
+                }
+                let mut failed = self.failed.lock().unwrap();
+                if !failed.is_empty() {
+                    ui.group(|ui| {
+                        ui.colored_label(ui.visuals().error_fg_color, Text::Failed.get(self.lang));
+                        for (file, reason) in failed.iter() {
+                            if let Some(path) = &file.path {
+                                ui.horizontal_wrapped(|ui| {
+                                    ui.label(path.display().to_string());
+                                    ui.weak(reason);
+                                });
+                            }
+                        }
+                        if ui.button(Text::RetryFailed.get(self.lang)).clicked() {
+                            let mut report = self.report.lock().unwrap();
+                            *report = DropReport::default();
+                            retry_failed(&mut failed, &mut dropped_files, &mut report);
+                        }
+                    });
+                }
             }
         });
 
@@ -72,12 +261,20 @@ impl eframe::App for MyApp {
                     *self.app_state.lock().unwrap() = AppState::Converting;
                     let ctx_store = ctx.clone();
                     let file = Arc::clone(&self.dropped_files);
+                    let settings = self.settings.clone();
+                    let report = Arc::clone(&self.report);
+                    let paused = Arc::clone(&self.paused);
+                    let failed = Arc::clone(&self.failed);
 
                     thread::spawn(move || {
-                        if let Err(e) = convert_to_mono(file, &ctx_store) {
-                            eprintln!("{}", e);
-                        }
-                        *state_store.lock().unwrap() = AppState::Idle;
+                        run_batch(&state_store, || {
+                            run_workers(settings.worker_count(), || {
+                                convert_to_mono(
+                                    &file, &ctx_store, &settings, &report, &paused, &failed,
+                                )
+                            })
+                        });
+                        ctx_store.request_repaint();
                     });
                 }
                 AppState::Converting => {
@@ -86,57 +283,325 @@ impl eframe::App for MyApp {
             }
         }
 
-        preview_files_being_dropped(ctx);
+        preview_files_being_dropped(ctx, self.lang);
 
         // Collect dropped files:
         ctx.input(|i| {
             if !i.raw.dropped_files.is_empty() {
                 let mut dropped_files = self.dropped_files.lock().unwrap();
-                for f in i.raw.dropped_files.iter() {
-                    dropped_files.push(f.clone());
+                let mut report = self.report.lock().unwrap();
+                // a drop onto an idle window starts a new batch
+                let idle = matches!(*self.app_state.lock().unwrap(), AppState::Idle);
+                if idle && dropped_files.is_empty() {
+                    *report = DropReport::default();
                 }
+                queue_dropped(&mut dropped_files, &i.raw.dropped_files, &mut report);
+                let paths = i.raw.dropped_files.iter().filter_map(|f| f.path.clone());
+                load_thumbnails(paths.collect(), &self.thumbnails, ctx);
+            }
+        });
+    }
+}
+
+// "3 of 10 files done, 1 failed" for the text progress mode.
+fn progress_text(report: &DropReport, pending: usize, lang: Lang) -> String {
+    let done = report.processed + report.skipped + report.unchanged + report.failed;
+    let mut text = Text::Progress
+        .get(lang)
+        .replace("{done}", &done.to_string())
+        .replace("{total}", &(done + pending).to_string());
+    if report.failed > 0 {
+        text += &Text::ProgressFailed
+            .get(lang)
+            .replace("{failed}", &report.failed.to_string());
+    }
+    text
+}
+
+// "2 dropped files skipped" once any of the batch was left out: not a
+// readable .wav, or passed over by the settings.
+fn skipped_text(report: &DropReport, lang: Lang) -> Option<String> {
+    if report.skipped == 0 {
+        return None;
+    }
+    let text = Text::Skipped
+        .get(lang)
+        .replace("{count}", &report.skipped.to_string());
+    Some(text)
+}
+
+// Runs `work` on `threads` workers sharing the queue and waits for all of
+// them. The first error any worker hit is returned.
+fn run_workers<F>(threads: usize, work: F) -> io::Result<()>
+where
+    F: Fn() -> io::Result<()> + Sync,
+{
+    thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads.max(1)).map(|_| scope.spawn(&work)).collect();
+        let mut result = Ok(());
+        for worker in workers {
+            let outcome = worker
+                .join()
+                .unwrap_or_else(|panic| panic::resume_unwind(panic));
+            if result.is_ok() {
+                result = outcome;
             }
+        }
+        result
+    })
+}
+
+// Runs a conversion and always returns the app to Idle afterwards, even if
+// it panics, so a bad file cannot leave the window stuck in Converting.
+fn run_batch(state: &Mutex<AppState>, convert: impl FnOnce() -> io::Result<()>) {
+    match panic::catch_unwind(AssertUnwindSafe(convert)) {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => eprintln!("{}", e),
+        Err(_) => eprintln!("Conversion stopped by a panic"),
+    }
+    *state.lock().unwrap() = AppState::Idle;
+}
+
+// Turns a panic in one file's conversion into an error for that file, so it
+// shows up in the Failed list and the rest of the batch carries on.
+fn catch_panic<T>(convert: impl FnOnce() -> io::Result<T>) -> io::Result<T> {
+    panic::catch_unwind(AssertUnwindSafe(convert)).unwrap_or_else(|panic| {
+        let message = panic
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| panic.downcast_ref::<String>().cloned())
+            .unwrap_or_default();
+        Err(io::Error::other(format!(
+            "Conversion stopped by a panic: {}",
+            message
+        )))
+    })
+}
+
+// Adds newly dropped files to the queue unless the same file, compared by
+// canonical path, is already waiting there. Only `.wav` files that exist
+// and can be opened are queued; the ones turned away are counted in
+// `report` so the window can show them straight away. A drop with neither a
+// path nor bytes can't be read at all and is counted as unsupported.
+fn queue_dropped(
+    pending: &mut Vec<egui::DroppedFile>,
+    dropped: &[egui::DroppedFile],
+    report: &mut DropReport,
+) {
+    let key = |file: &egui::DroppedFile| {
+        let path = file.path.as_ref()?;
+        Some(path.canonicalize().unwrap_or_else(|_| path.clone()))
+    };
+    for file in dropped {
+        if file.path.is_none() && file.bytes.is_none() {
+            report.unsupported += 1;
+            continue;
+        }
+        let readable = file.path.as_ref().is_some_and(|path| {
+            path.extension().unwrap_or_default() == "wav" && File::open(path).is_ok()
         });
+        if !readable {
+            report.skipped += 1;
+            continue;
+        }
+        let new = key(file);
+        if pending.iter().any(|f| key(f) == new) {
+            continue;
+        }
+        pending.push(file.clone());
     }
 }
 
 fn convert_to_mono(
-    files: Arc<Mutex<Vec<egui::DroppedFile>>>,
+    files: &Mutex<Vec<egui::DroppedFile>>,
     ctx: &egui::Context,
+    settings: &ConvertSettings,
+    report: &Mutex<DropReport>,
+    paused: &AtomicBool,
+    failed: &Mutex<Vec<(egui::DroppedFile, String)>>,
 ) -> io::Result<()> {
+    let mut repaint = RepaintThrottle::default();
     loop {
-        if files.lock().unwrap().is_empty() {
-            break;
-        }
-
-        let file = files.lock().unwrap().remove(0);
-        let Some(input) = file.path else {
+        // one lock for check and take, as other workers share the queue.
+        // An empty queue ends the batch even while paused, as the Resume
+        // button goes away with the queue.
+        let file = {
+            let mut files = files.lock().unwrap();
+            if files.is_empty() {
+                break;
+            }
+            if paused.load(Ordering::Relaxed) {
+                drop(files);
+                thread::sleep(PAUSE_POLL);
+                continue;
+            }
+            files.remove(0)
+        };
+        let output = file
+            .path
+            .clone()
+            .filter(|input| input.extension().unwrap_or_default() == "wav")
+            .and_then(|input| Some((mono_output_path(&input)?, input)));
+        let Some((output, input)) = output else {
+            report.lock().unwrap().skipped += 1;
             continue;
         };
+        // a failed file is kept for Retry failed instead of ending the batch
+        match catch_panic(|| convert_file(&input, output, settings)) {
+            Ok(Converted::Processed) => report.lock().unwrap().processed += 1,
+            Ok(Converted::Skipped) => report.lock().unwrap().skipped += 1,
+            Ok(Converted::Unchanged) => report.lock().unwrap().unchanged += 1,
+            Err(e) => {
+                report.lock().unwrap().failed += 1;
+                failed.lock().unwrap().push((file, e.to_string()));
+            }
+        }
+        if repaint.ready(Instant::now()) {
+            ctx.request_repaint();
+        }
+    }
+    Ok(())
+}
 
-        if input.extension().unwrap_or_default() != "wav" {
-            continue;
+enum Converted {
+    Processed,
+    Skipped,
+    Unchanged,
+}
+
+fn convert_file(
+    input: &Path,
+    mut output: PathBuf,
+    settings: &ConvertSettings,
+) -> io::Result<Converted> {
+    let source = read_header(input)?;
+    if settings.skip_multichannel && source.channel_count > 2 {
+        return Ok(Converted::Skipped);
+    }
+    if let Some(folder) = settings.channel_folder(source.channel_count) {
+        output = output_path(input, folder).unwrap();
+    }
+    let mut clipped = output_path(input, "clipped").unwrap();
+    if settings.route_by_rate {
+        output = rate_folder(&output, source.sampling_rate);
+        clipped = rate_folder(&clipped, source.sampling_rate);
+    }
+    check_distinct(input, &output)?;
+    if is_already_converted(input, &output)?
+        || (settings.route_clipped && is_already_converted(input, &clipped)?)
+    {
+        return Ok(Converted::Unchanged);
+    }
+    let (header, data) = if settings.decode_g711 {
+        open_wav_decoded(input)?
+    } else if settings.repair {
+        let (header, data, _) = open_wav_repaired(input)?;
+        (header, data)
+    } else {
+        open_wav(input)?
+    };
+    let mut wav = Wav::new(header, data);
+    if settings.route_clipped && wav.clip_ratio() > CLIPPED_RATIO {
+        output = clipped;
+        check_distinct(input, &output)?;
+    }
+    wav.to_mono();
+    if settings.add_comment {
+        wav.with_comment(&conversion_comment());
+    }
+    if settings.keep_loops {
+        wav.keep_sample_chunk(input)?;
+    }
+    wav.write(&output)?;
+    Ok(Converted::Processed)
+}
+
+// `<dir>/<folder>/<rate>/<name>` for an output at `<dir>/<folder>/<name>`.
+fn rate_folder(output: &Path, rate: u32) -> PathBuf {
+    let name = output.file_name().unwrap_or_default();
+    output.with_file_name(rate.to_string()).join(name)
+}
+
+// Puts the files that failed back in the queue, e.g. after fixing
+// permissions or reconnecting a drive. Files that are still unreadable are
+// turned away as by queue_dropped.
+fn retry_failed(
+    failed: &mut Vec<(egui::DroppedFile, String)>,
+    pending: &mut Vec<egui::DroppedFile>,
+    report: &mut DropReport,
+) {
+    let files: Vec<_> = failed.drain(..).map(|(file, _)| file).collect();
+    queue_dropped(pending, &files, report)
+}
+
+const PAUSE_POLL: Duration = Duration::from_millis(50);
+
+// At most one repaint per interval while a batch runs, so a folder of tiny
+// files doesn't flood the UI thread. The worker repaints once more when the
+// batch ends, which shows the final state.
+#[derive(Debug, Default)]
+struct RepaintThrottle {
+    last: Option<Instant>,
+}
+
+const REPAINT_INTERVAL: Duration = Duration::from_millis(100);
+
+impl RepaintThrottle {
+    fn ready(&mut self, now: Instant) -> bool {
+        match self.last {
+            Some(last) if now.duration_since(last) < REPAINT_INTERVAL => false,
+            _ => {
+                self.last = Some(now);
+                true
+            }
         }
+    }
+}
+
+// Reads the envelopes on a separate thread so large files don't stall the UI.
+fn load_thumbnails(paths: Vec<PathBuf>, thumbnails: &Thumbnails, ctx: &egui::Context) {
+    let thumbnails = Arc::clone(thumbnails);
+    let ctx = ctx.clone();
+    thread::spawn(move || {
+        for path in paths {
+            if path.extension().unwrap_or_default() != "wav" {
+                continue;
+            }
+            if let Ok((header, data)) = open_wav(&path) {
+                let envelope = peak_envelope(&header, &data, THUMBNAIL_BUCKETS);
+                thumbnails.lock().unwrap().insert(path, envelope);
+                ctx.request_repaint();
+            }
+        }
+    });
+}
 
-        let output = input
-            .parent()
-            .unwrap()
-            .join("mono")
-            .join(input.file_name().unwrap());
-        Wav::open(&input).to_mono().write(&output)?;
-        ctx.request_repaint();
+// One lane per channel, so a silent side shows up as a flat line.
+fn draw_thumbnail(ui: &mut egui::Ui, envelope: &[Vec<f32>]) {
+    let (rect, _) = ui.allocate_exact_size(egui::vec2(48.0, 16.0), egui::Sense::hover());
+    let painter = ui.painter_at(rect);
+    painter.rect_filled(rect, 2.0, ui.visuals().extreme_bg_color);
+    let stroke = egui::Stroke::new(1.0, ui.visuals().text_color());
+    let lane = rect.height() / envelope.len().max(1) as f32;
+    for (c, peaks) in envelope.iter().enumerate() {
+        let mid = rect.top() + lane * (c as f32 + 0.5);
+        let step = rect.width() / peaks.len().max(1) as f32;
+        for (i, &peak) in peaks.iter().enumerate() {
+            let x = rect.left() + step * (i as f32 + 0.5);
+            let h = (peak * lane / 2.0).max(0.5);
+            painter.line_segment([egui::pos2(x, mid - h), egui::pos2(x, mid + h)], stroke);
+        }
     }
-    Ok(())
 }
 
 /// Preview hovering files:
-fn preview_files_being_dropped(ctx: &egui::Context) {
+fn preview_files_being_dropped(ctx: &egui::Context, lang: Lang) {
     use egui::*;
     use std::fmt::Write as _;
 
     if !ctx.input(|i| i.raw.hovered_files.is_empty()) {
         let text = ctx.input(|i| {
-            let mut text = "Dropping files:\n".to_owned();
+            let mut text = format!("{}\n", Text::DroppingFiles.get(lang));
             for file in &i.raw.hovered_files {
                 if let Some(path) = &file.path {
                     write!(text, "\n{}", path.display()).ok();
@@ -185,6 +650,442 @@ fn main() -> eframe::Result<()> {
     eframe::run_native(
         concat!("wav2mono ver", env!("CARGO_PKG_VERSION")),
         native_options,
-        Box::new(|_cc| Box::<MyApp>::default()),
+        Box::new(|cc| {
+            i18n::install_japanese_font(&cc.egui_ctx);
+            let lang = i18n::settings_path().map_or_else(Lang::default, |p| i18n::load_lang(&p));
+            Box::new(MyApp {
+                lang,
+                ..Default::default()
+            })
+        }),
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dropped(path: &str) -> egui::DroppedFile {
+        egui::DroppedFile {
+            path: Some(path.into()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_pause_holds_the_queue() {
+        let files = Arc::new(Mutex::new(vec![dropped("a.txt"), dropped("b.txt")]));
+        let paused = Arc::new(AtomicBool::new(true));
+        let worker = {
+            let (files, paused) = (Arc::clone(&files), Arc::clone(&paused));
+            thread::spawn(move || {
+                let report = Mutex::new(DropReport::default());
+                let ctx = egui::Context::default();
+                let failed = Mutex::new(Vec::new());
+                convert_to_mono(
+                    &files,
+                    &ctx,
+                    &ConvertSettings::default(),
+                    &report,
+                    &paused,
+                    &failed,
+                )
+                .unwrap();
+                report.into_inner().unwrap()
+            })
+        };
+        thread::sleep(PAUSE_POLL * 3);
+        assert_eq!(files.lock().unwrap().len(), 2);
+
+        paused.store(false, Ordering::Relaxed);
+        assert_eq!(worker.join().unwrap().skipped, 2);
+        assert!(files.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_pause_with_an_empty_queue_ends_the_batch() {
+        // the last file was taken just before Pause was clicked
+        convert_to_mono(
+            &Mutex::new(Vec::new()),
+            &egui::Context::default(),
+            &ConvertSettings::default(),
+            &Mutex::new(DropReport::default()),
+            &AtomicBool::new(true),
+            &Mutex::new(Vec::new()),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_worker_count_is_respected() {
+        use std::sync::atomic::AtomicUsize;
+
+        let (active, most) = (AtomicUsize::new(0), AtomicUsize::new(0));
+        let queue = Mutex::new((0..12).collect::<Vec<_>>());
+        run_workers(3, || {
+            while queue.lock().unwrap().pop().is_some() {
+                let now = active.fetch_add(1, Ordering::SeqCst) + 1;
+                most.fetch_max(now, Ordering::SeqCst);
+                thread::sleep(Duration::from_millis(10));
+                active.fetch_sub(1, Ordering::SeqCst);
+            }
+            Ok(())
+        })
+        .unwrap();
+        assert!(queue.lock().unwrap().is_empty());
+        assert!(most.load(Ordering::SeqCst) <= 3);
+        assert!(most.load(Ordering::SeqCst) > 1);
+    }
+
+    #[test]
+    fn test_progress_text() {
+        let report = DropReport {
+            processed: 2,
+            skipped: 1,
+            ..Default::default()
+        };
+        assert_eq!(
+            progress_text(&report, 7, Lang::English),
+            "3 of 10 files done"
+        );
+        let report = DropReport {
+            processed: 8,
+            failed: 2,
+            ..Default::default()
+        };
+        assert_eq!(
+            progress_text(&report, 0, Lang::English),
+            "10 of 10 files done, 2 failed"
+        );
+        assert_eq!(
+            progress_text(&DropReport::default(), 4, Lang::Japanese),
+            "4 件中 0 件完了"
+        );
+    }
+
+    #[test]
+    fn test_repaints_are_coalesced() {
+        let mut throttle = RepaintThrottle::default();
+        let start = Instant::now();
+        let rapid = (0..50)
+            .filter(|&i| throttle.ready(start + Duration::from_millis(i)))
+            .count();
+        assert_eq!(rapid, 1);
+        assert!(throttle.ready(start + REPAINT_INTERVAL));
+    }
+
+    #[test]
+    fn test_panic_becomes_an_error() {
+        let e = catch_panic(|| -> io::Result<()> { panic!("unreadable file") }).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "Conversion stopped by a panic: unreadable file"
+        );
+        let e = catch_panic(|| -> io::Result<()> { panic!("{} samples", 3) }).unwrap_err();
+        assert!(e.to_string().ends_with("3 samples"));
+        assert_eq!(catch_panic(|| Ok(1)).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_panicking_conversion_returns_to_idle() {
+        let state = Mutex::new(AppState::Converting);
+        run_batch(&state, || panic!("unreadable file"));
+        assert!(matches!(*state.lock().unwrap(), AppState::Idle));
+    }
+
+    #[test]
+    fn test_dropping_a_file_twice_queues_it_once() {
+        let mut pending = vec![dropped("test/test.wav")];
+        queue_dropped(
+            &mut pending,
+            &[dropped("test/../test/test.wav"), dropped("test/test.wav")],
+            &mut DropReport::default(),
+        );
+        assert_eq!(pending.len(), 1);
+    }
+
+    #[test]
+    fn test_failed_files_can_be_retried() {
+        let dir = Path::new("test/gui_retry");
+        let _ = std::fs::remove_dir_all(dir);
+        std::fs::create_dir_all(dir).unwrap();
+        let broken = dir.join("broken.wav");
+        std::fs::write(&broken, b"not a wav file").unwrap();
+
+        let files = Mutex::new(vec![dropped(broken.to_str().unwrap())]);
+        let (report, failed) = (Mutex::new(DropReport::default()), Mutex::new(Vec::new()));
+        let ctx = egui::Context::default();
+        let settings = ConvertSettings::default();
+        let paused = AtomicBool::new(false);
+        convert_to_mono(&files, &ctx, &settings, &report, &paused, &failed).unwrap();
+        assert_eq!(report.lock().unwrap().failed, 1);
+
+        let mut failed = failed.into_inner().unwrap();
+        // the reason is kept for the Failed list
+        assert!(!failed[0].1.is_empty());
+        let mut pending = files.into_inner().unwrap();
+        let mut report = DropReport::default();
+        retry_failed(&mut failed, &mut pending, &mut report);
+        assert_eq!(report.skipped, 0);
+        assert!(failed.is_empty());
+        assert_eq!(pending[0].path.as_deref(), Some(broken.as_path()));
+    }
+
+    #[test]
+    fn test_bad_file_does_not_stop_the_batch() {
+        let dir = Path::new("test/gui_continue");
+        let _ = std::fs::remove_dir_all(dir);
+        std::fs::create_dir_all(dir).unwrap();
+        let header = wav::Header::new(wav::header::WAV_FORMAT_PCM, 2, 44100, 16);
+        let data = wav::BitDepth::Sixteen(vec![1000, -1000, 2000, -2000]);
+        for name in ["a.wav", "c.wav"] {
+            Wav::new(header, data.clone())
+                .write(&dir.join(name))
+                .unwrap();
+        }
+        std::fs::write(dir.join("b.wav"), b"not a wav file").unwrap();
+
+        let names = ["a.wav", "b.wav", "c.wav"];
+        let files = Mutex::new(
+            names
+                .map(|n| dropped(dir.join(n).to_str().unwrap()))
+                .to_vec(),
+        );
+        let (report, failed) = (Mutex::new(DropReport::default()), Mutex::new(Vec::new()));
+        let ctx = egui::Context::default();
+        let paused = AtomicBool::new(false);
+        convert_to_mono(
+            &files,
+            &ctx,
+            &ConvertSettings::default(),
+            &report,
+            &paused,
+            &failed,
+        )
+        .unwrap();
+
+        let report = *report.lock().unwrap();
+        assert_eq!((report.processed, report.failed), (2, 1));
+        assert!(dir.join("mono/c.wav").exists());
+    }
+
+    #[test]
+    fn test_only_readable_wav_files_are_queued() {
+        let mut pending = Vec::new();
+        let mut report = DropReport::default();
+        queue_dropped(
+            &mut pending,
+            &[
+                dropped("test/test.wav"),
+                dropped("test/missing.wav"),
+                dropped("Cargo.toml"),
+            ],
+            &mut report,
+        );
+        assert_eq!(report.skipped, 2);
+        let paths: Vec<_> = pending.iter().map(|f| f.path.clone().unwrap()).collect();
+        assert_eq!(paths, [PathBuf::from("test/test.wav")]);
+    }
+
+    #[test]
+    fn test_skipped_drops_are_shown() {
+        let mut pending = Vec::new();
+        let mut report = DropReport::default();
+        let mut drop = vec![dropped("test/test.wav"); 5];
+        drop.push(dropped("test/missing.wav"));
+        queue_dropped(&mut pending, &drop, &mut report);
+        assert_eq!(
+            skipped_text(&report, Lang::English).as_deref(),
+            Some("1 dropped file(s) skipped")
+        );
+        assert_eq!(skipped_text(&DropReport::default(), Lang::English), None);
+    }
+
+    #[test]
+    fn test_drop_without_wav_files_is_reported() {
+        let files = Arc::new(Mutex::new(vec![
+            dropped("notes.txt"),
+            dropped("readme.txt"),
+        ]));
+        let report = Mutex::new(DropReport::default());
+        convert_to_mono(
+            &files,
+            &egui::Context::default(),
+            &ConvertSettings::default(),
+            &report,
+            &AtomicBool::new(false),
+            &Mutex::new(Vec::new()),
+        )
+        .unwrap();
+
+        let report = *report.lock().unwrap();
+        assert_eq!(
+            report,
+            DropReport {
+                processed: 0,
+                skipped: 2,
+                unchanged: 0,
+                failed: 0,
+                unsupported: 0
+            }
+        );
+        assert!(report.nothing_to_convert());
+    }
+
+    #[test]
+    fn test_failed_wav_is_not_reported_as_missing() {
+        let dir = Path::new("test/gui_failed_only");
+        let _ = std::fs::remove_dir_all(dir);
+        std::fs::create_dir_all(dir).unwrap();
+        let broken = dir.join("broken.wav");
+        std::fs::write(&broken, b"not a wav file").unwrap();
+
+        let files = Mutex::new(vec![
+            dropped(broken.to_str().unwrap()),
+            dropped("notes.txt"),
+        ]);
+        let report = Mutex::new(DropReport::default());
+        convert_to_mono(
+            &files,
+            &egui::Context::default(),
+            &ConvertSettings::default(),
+            &report,
+            &AtomicBool::new(false),
+            &Mutex::new(Vec::new()),
+        )
+        .unwrap();
+
+        let report = *report.lock().unwrap();
+        assert_eq!((report.failed, report.skipped), (1, 1));
+        assert!(!report.nothing_to_convert());
+    }
+
+    #[test]
+    fn test_clipped_output_counts_as_converted() {
+        let dir = Path::new("test/gui_clipped");
+        let _ = std::fs::remove_dir_all(dir);
+        let input = dir.join("hot.wav");
+        let header = wav::Header::new(wav::header::WAV_FORMAT_PCM, 2, 44100, 16);
+        let data = wav::BitDepth::Sixteen(vec![i16::MAX; 8]);
+        Wav::new(header, data).write(&input).unwrap();
+        let settings = ConvertSettings {
+            route_clipped: true,
+            ..Default::default()
+        };
+
+        let output = mono_output_path(&input).unwrap();
+        let first = convert_file(&input, output.clone(), &settings).unwrap();
+        assert!(matches!(first, Converted::Processed));
+        assert!(dir.join("clipped/hot.wav").exists());
+        let again = convert_file(&input, output, &settings).unwrap();
+        assert!(matches!(again, Converted::Unchanged));
+    }
+
+    #[test]
+    fn test_outputs_sorted_by_rate() {
+        let dir = Path::new("test/gui_rates");
+        let _ = std::fs::remove_dir_all(dir);
+        let settings = ConvertSettings {
+            route_by_rate: true,
+            ..Default::default()
+        };
+        for (name, rate) in [("speech.wav", 16000), ("music.wav", 44100)] {
+            let input = dir.join(name);
+            let header = wav::Header::new(wav::header::WAV_FORMAT_PCM, 2, rate, 16);
+            let data = wav::BitDepth::Sixteen(vec![1000, -1000, 2000, -2000]);
+            Wav::new(header, data).write(&input).unwrap();
+            let output = mono_output_path(&input).unwrap();
+            convert_file(&input, output, &settings).unwrap();
+        }
+        assert!(dir.join("mono/16000/speech.wav").exists());
+        assert!(dir.join("mono/44100/music.wav").exists());
+        assert!(!dir.join("mono/speech.wav").exists());
+    }
+
+    #[test]
+    fn test_channel_count_picks_the_folder() {
+        let dir = Path::new("test/gui_channel_folders");
+        let _ = std::fs::remove_dir_all(dir);
+        let input = dir.join("ambisonic.wav");
+        let header = wav::Header::new(wav::header::WAV_FORMAT_PCM, 4, 48000, 16);
+        let data = wav::BitDepth::Sixteen(vec![1000, -1000, 2000, -2000]);
+        Wav::new(header, data).write(&input).unwrap();
+        let settings = ConvertSettings {
+            channel_folders: "6=surround, 4 = quad, bad".to_owned(),
+            ..Default::default()
+        };
+        assert_eq!(settings.channel_folder(2), None);
+
+        let output = mono_output_path(&input).unwrap();
+        convert_file(&input, output, &settings).unwrap();
+        assert!(dir.join("quad/ambisonic.wav").exists());
+        assert!(!dir.join("mono/ambisonic.wav").exists());
+    }
+
+    #[test]
+    fn test_mulaw_decoded_when_asked() {
+        let dir = Path::new("test/gui_mulaw");
+        let _ = std::fs::remove_dir_all(dir);
+        std::fs::create_dir_all(dir).unwrap();
+        let input = dir.join("phone.wav");
+        let mut header = wav::Header::new(7, 2, 8000, 8);
+        header.bytes_per_sample = 2;
+        let fmt: [u8; 16] = header.into();
+        let mut bytes = b"RIFF\x2a\0\0\0WAVEfmt \x10\0\0\0".to_vec();
+        bytes.extend(fmt);
+        bytes.extend(b"data\x06\0\0\0\x80\x80\xf0\xf0\0\0");
+        std::fs::write(&input, bytes).unwrap();
+        let output = mono_output_path(&input).unwrap();
+
+        assert!(convert_file(&input, output.clone(), &ConvertSettings::default()).is_err());
+        let settings = ConvertSettings {
+            decode_g711: true,
+            ..Default::default()
+        };
+        convert_file(&input, output.clone(), &settings).unwrap();
+        let (header, data) = open_wav(&output).unwrap();
+        assert_eq!(header.channel_count, 1);
+        assert_eq!(data, wav::BitDepth::Sixteen(vec![32124, 120, -32124]));
+    }
+
+    #[test]
+    fn test_truncated_data_size_repaired_when_asked() {
+        let dir = Path::new("test/gui_repair");
+        let _ = std::fs::remove_dir_all(dir);
+        let input = dir.join("crashed.wav");
+        let header = wav::Header::new(wav::header::WAV_FORMAT_PCM, 2, 48000, 16);
+        let data = wav::BitDepth::Sixteen(vec![1000, -1000, 2000, -2000]);
+        Wav::new(header, data).write(&input).unwrap();
+        let mut bytes = std::fs::read(&input).unwrap();
+        let size = bytes.windows(4).position(|w| w == b"data").unwrap() + 4;
+        bytes[size..size + 4].fill(0);
+        std::fs::write(&input, bytes).unwrap();
+        let settings = ConvertSettings {
+            repair: true,
+            ..Default::default()
+        };
+
+        let output = mono_output_path(&input).unwrap();
+        convert_file(&input, output.clone(), &settings).unwrap();
+        let (_, data) = open_wav(&output).unwrap();
+        assert_eq!(data, wav::BitDepth::Sixteen(vec![1000, 2000]));
+    }
+
+    #[test]
+    fn test_drop_without_path_or_bytes_is_unsupported() {
+        let mut pending = Vec::new();
+        let mut report = DropReport::default();
+        let named = egui::DroppedFile {
+            name: "clip.wav".to_owned(),
+            ..Default::default()
+        };
+        queue_dropped(
+            &mut pending,
+            &[egui::DroppedFile::default(), named],
+            &mut report,
+        );
+        assert!(pending.is_empty());
+        assert_eq!((report.unsupported, report.skipped), (2, 0));
+        assert!(report.nothing_to_convert());
+    }
+}
@@ -0,0 +1,53 @@
+// Fixture helpers shared by the tests across the crate.
+use std::io::Cursor;
+use std::path::Path;
+
+use wav::header::WAV_FORMAT_PCM;
+use wav::{BitDepth, Header};
+
+use crate::Wav;
+
+// Encodes a complete WAV file in memory.
+pub fn build_wav(header: Header, data: &BitDepth) -> Vec<u8> {
+    let mut bytes = Cursor::new(Vec::new());
+    wav::write(header, data, &mut bytes).unwrap();
+    bytes.into_inner()
+}
+
+// Interleaves 16-bit frames, one slice of samples per frame.
+pub fn pcm16<F: AsRef<[i16]>>(frames: &[F]) -> BitDepth {
+    BitDepth::Sixteen(frames.iter().flat_map(|f| f.as_ref().to_vec()).collect())
+}
+
+// Writes a fixture file, creating its directory.
+pub fn write_wav_file(path: &Path, header: Header, data: &BitDepth) {
+    Wav::new(header, data.clone()).write(path).unwrap();
+}
+
+// A short 16-bit 44.1 kHz stereo file.
+pub fn write_stereo(path: &Path) {
+    let header = Header::new(WAV_FORMAT_PCM, 2, 44100, 16);
+    write_wav_file(
+        path,
+        header,
+        &pcm16(&[[1000, -1000], [2000, 1500], [-3000, 500]]),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_wav_round_trip() {
+        let header = Header::new(WAV_FORMAT_PCM, 2, 48000, 16);
+        let data = pcm16(&[[100, -100], [i16::MAX, i16::MIN]]);
+        let bytes = build_wav(header, &data);
+        let (read_header, read_data) = wav::read(&mut Cursor::new(bytes)).unwrap();
+        assert_eq!(read_header, header);
+        assert_eq!(
+            read_data,
+            BitDepth::Sixteen(vec![100, -100, i16::MAX, i16::MIN])
+        );
+    }
+}